@@ -0,0 +1,352 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use mime_sniff;
+
+/// How far into a page `<meta charset>` is looked for.
+static PRESCAN_BYTES: usize = 1024;
+/// How much of a page is given to the detector when nothing says what it is,
+/// and so all that's looked at to decide a page's encoding.
+pub static DETECT_BYTES: usize = 64 * 1024;
+
+/// Where a page's encoding came from, in the order they're tried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodingSource {
+    Bom,
+    Header,
+    Meta,
+    Detected,
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\n' || c == b'\x0c' || c == b'\r'
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Finds the `charset=` in a Content-Type or `<meta content>`, if there is one.
+pub fn charset_parameter(value: &[u8]) -> Option<&[u8]> {
+    let mut position = 0;
+    while position + 7 <= value.len() {
+        if !starts_with_ignore_case(&value[position..], b"charset") {
+            position += 1;
+            continue;
+        }
+
+        position += 7;
+        while position < value.len() && is_whitespace(value[position]) {
+            position += 1;
+        }
+        if position >= value.len() || value[position] != b'=' {
+            continue;
+        }
+        position += 1;
+        while position < value.len() && is_whitespace(value[position]) {
+            position += 1;
+        }
+
+        let rest = &value[position..];
+        return match rest.first() {
+            Some(&quote) if quote == b'"' || quote == b'\'' => rest[1..]
+                .iter()
+                .position(|x| *x == quote)
+                .map(|end| &rest[1..end + 1]),
+            Some(_) => {
+                let end = rest
+                    .iter()
+                    .position(|x| is_whitespace(*x) || *x == b';')
+                    .unwrap_or(rest.len());
+                Some(&rest[..end])
+            }
+            None => None,
+        };
+    }
+
+    return None;
+}
+
+// the HTML spec's "get an attribute", which lowercases names and unquoted values
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    while *position < bytes.len() && (is_whitespace(bytes[*position]) || bytes[*position] == b'/') {
+        *position += 1;
+    }
+    if *position >= bytes.len() || bytes[*position] == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+    loop {
+        let c = match bytes.get(*position) {
+            Some(c) => *c,
+            None => return None,
+        };
+        if c == b'=' && name.len() > 0 {
+            break;
+        } else if is_whitespace(c) {
+            while *position < bytes.len() && is_whitespace(bytes[*position]) {
+                *position += 1;
+            }
+            if bytes.get(*position) != Some(&b'=') {
+                return Some((name, Vec::new()));
+            }
+            break;
+        } else if c == b'/' || c == b'>' {
+            return Some((name, Vec::new()));
+        }
+
+        name.push(c.to_ascii_lowercase());
+        *position += 1;
+    }
+    // skip the =
+    *position += 1;
+
+    while *position < bytes.len() && is_whitespace(bytes[*position]) {
+        *position += 1;
+    }
+
+    let mut value = Vec::new();
+    match bytes.get(*position) {
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+            *position += 1;
+            while *position < bytes.len() && bytes[*position] != quote {
+                value.push(bytes[*position].to_ascii_lowercase());
+                *position += 1;
+            }
+            *position += 1;
+        }
+        Some(&b'>') | None => {}
+        Some(_) => {
+            while *position < bytes.len()
+                && !is_whitespace(bytes[*position])
+                && bytes[*position] != b'>'
+            {
+                value.push(bytes[*position].to_ascii_lowercase());
+                *position += 1;
+            }
+        }
+    }
+
+    return Some((name, value));
+}
+
+// works out what a <meta> says the encoding is, from its attributes
+fn meta_encoding(bytes: &[u8], position: &mut usize) -> Option<&'static Encoding> {
+    let mut names: Vec<Vec<u8>> = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(bytes, position) {
+        if names.contains(&name) {
+            continue;
+        }
+
+        match name.as_slice() {
+            b"http-equiv" => got_pragma = got_pragma || value == b"content-type",
+            b"content" if charset.is_none() => {
+                if let Some(label) = charset_parameter(&value) {
+                    charset = Encoding::for_label(label);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        names.push(name);
+    }
+
+    if need_pragma == None || (need_pragma == Some(true) && !got_pragma) {
+        return None;
+    }
+
+    // a page that says it's UTF-16 in ASCII can't be
+    return charset.map(|encoding| {
+        if encoding == UTF_16BE || encoding == UTF_16LE {
+            UTF_8
+        } else if encoding == X_USER_DEFINED {
+            WINDOWS_1252
+        } else {
+            encoding
+        }
+    });
+}
+
+/// Looks for a `<meta charset>` or `<meta http-equiv=content-type>` near the
+/// start of a page, as in the HTML spec's prescan.
+pub fn prescan(body: &[u8]) -> Option<&'static Encoding> {
+    let bytes = &body[..body.len().min(PRESCAN_BYTES)];
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        if rest.starts_with(b"<!--") {
+            position += rest[2..]
+                .windows(3)
+                .position(|x| x == b"-->")
+                .map(|x| x + 5)
+                .unwrap_or(rest.len());
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .map_or(false, |x| is_whitespace(*x) || *x == b'/')
+        {
+            position += 5;
+            if let Some(encoding) = meta_encoding(bytes, &mut position) {
+                return Some(encoding);
+            }
+        } else if rest.len() > 1
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).map_or(false, |x| x.is_ascii_alphabetic())))
+            && rest[0] == b'<'
+        {
+            // skip the tag's name and attributes
+            position += rest
+                .iter()
+                .position(|x| is_whitespace(*x) || *x == b'>')
+                .unwrap_or(rest.len());
+            while get_attribute(bytes, &mut position).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position += rest.iter().position(|x| *x == b'>').unwrap_or(rest.len());
+        } else {
+            position += 1;
+        }
+    }
+
+    return None;
+}
+
+/// Works out a body's encoding: a byte order mark wins, then the Content-Type's
+/// charset, then (for HTML) a `<meta charset>`, and failing all those, a guess.
+pub fn sniff(body: &[u8], content_type: &str) -> (&'static Encoding, EncodingSource) {
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return (encoding, EncodingSource::Bom);
+    }
+
+    if let Some(encoding) =
+        charset_parameter(content_type.as_bytes()).and_then(|x| Encoding::for_label(x))
+    {
+        return (encoding, EncodingSource::Header);
+    }
+
+    if mime_sniff::essence(content_type) == "" || mime_sniff::is_html(content_type) {
+        if let Some(encoding) = prescan(body) {
+            return (encoding, EncodingSource::Meta);
+        }
+    }
+
+    let mut detector = EncodingDetector::new();
+    let sample = &body[..body.len().min(DETECT_BYTES)];
+    detector.feed(sample, sample.len() == body.len());
+    return (detector.guess(None, true), EncodingSource::Detected);
+}
+
+/// Decodes a body to UTF-8, returning the text and the encoding it was in.
+pub fn decode(body: &[u8], content_type: &str) -> (String, &'static Encoding) {
+    let (encoding, source) = sniff(body, content_type);
+    debug!("decoding as {} ({:?})", encoding.name(), source);
+
+    return (decode_with(encoding, body), encoding);
+}
+
+/// Decodes a body whose encoding is already known, dropping any byte order mark.
+pub fn decode_with(encoding: &'static Encoding, body: &[u8]) -> String {
+    let body = match Encoding::for_bom(body) {
+        Some((_, length)) => &body[length..],
+        None => body,
+    };
+    return encoding.decode_without_bom_handling(body).0.into_owned();
+}
+
+#[cfg(test)]
+mod tests {
+    use charset::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1251};
+
+    #[test]
+    fn _charset_parameter() {
+        assert_eq!(
+            charset_parameter(b"text/html; charset=Shift_JIS"),
+            Some(&b"Shift_JIS"[..])
+        );
+        assert_eq!(
+            charset_parameter(b"text/html;charset = \"utf-8\"; x=y"),
+            Some(&b"utf-8"[..])
+        );
+        assert_eq!(charset_parameter(b"text/html; charset=\"utf-8"), None);
+        assert_eq!(charset_parameter(b"text/html"), None);
+    }
+
+    #[test]
+    fn _prescan() {
+        assert_eq!(prescan(b"<meta charset=shift_jis>"), Some(SHIFT_JIS));
+        assert_eq!(
+            prescan(
+                b"<!DOCTYPE html><html lang='ru'><!-- <meta charset=utf-8> -->\
+                  <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=windows-1251\">"
+            ),
+            Some(WINDOWS_1251)
+        );
+        // content without http-equiv doesn't count
+        assert_eq!(
+            prescan(b"<meta content='text/html; charset=windows-1251'>"),
+            None
+        );
+        // neither does a charset in some other tag's attribute
+        assert_eq!(prescan(b"<div title='<meta charset=shift_jis>'>"), None);
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(
+            prescan(b"<meta charset=x-user-defined>"),
+            Some(WINDOWS_1252)
+        );
+    }
+
+    #[test]
+    fn _sniff() {
+        let page = b"<meta charset=windows-1251>\xcf\xf0\xe8\xe2\xe5\xf2";
+        assert_eq!(
+            sniff(b"\xef\xbb\xbfabc", "text/html; charset=windows-1251"),
+            (UTF_8, EncodingSource::Bom)
+        );
+        assert_eq!(
+            sniff(page, "text/html; charset=Shift_JIS"),
+            (SHIFT_JIS, EncodingSource::Header)
+        );
+        assert_eq!(
+            sniff(page, "text/html"),
+            (WINDOWS_1251, EncodingSource::Meta)
+        );
+        assert_eq!(sniff(page, "text/plain").1, EncodingSource::Detected);
+        assert_eq!(
+            sniff("ありがとうございます".as_bytes(), ""),
+            (UTF_8, EncodingSource::Detected)
+        );
+        assert_eq!(sniff(b"\xff\xfea\x00", "").0, UTF_16LE);
+    }
+
+    #[test]
+    fn _decode() {
+        assert_eq!(
+            decode(
+                b"<meta charset=windows-1251>\xcf\xf0\xe8\xe2\xe5\xf2",
+                "text/html"
+            ),
+            (
+                "<meta charset=windows-1251>Привет".to_string(),
+                WINDOWS_1251
+            )
+        );
+        assert_eq!(
+            decode(b"\xef\xbb\xbfh\xc3\xa9llo", ""),
+            ("héllo".to_string(), UTF_8)
+        );
+        assert_eq!(
+            decode(b"\x82\xa0\x82\xa2", "text/plain; charset=Shift_JIS"),
+            ("あい".to_string(), SHIFT_JIS)
+        );
+    }
+}
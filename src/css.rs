@@ -0,0 +1,246 @@
+use charset;
+use document::CrawlResult;
+use handler::{content_type, ContentHandler};
+use link::Link;
+use reqwest::header::Headers;
+use url::Url;
+
+/// Where in a stylesheet a URL was found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CssReference {
+    /// A `url(...)`, for a background image, font...
+    Url,
+    /// The stylesheet of an `@import`.
+    Import,
+}
+
+impl CssReference {
+    /// What's recorded as the link's attribute.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            CssReference::Url => "url",
+            CssReference::Import => "import",
+        }
+    }
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn skip_whitespace(bytes: &[u8], mut position: usize) -> usize {
+    while position < bytes.len() && (bytes[position] as char).is_ascii_whitespace() {
+        position += 1;
+    }
+    return position;
+}
+
+// reads a quoted string starting at `position`, returning it and where it ends
+fn read_string(css: &str, position: usize) -> Option<(String, usize)> {
+    let bytes = css.as_bytes();
+    let quote = bytes[position];
+    let mut value = String::new();
+    let mut escaped = false;
+
+    for (offset, c) in css[position + 1..].char_indices() {
+        if escaped {
+            // a backslash before a newline continues the string
+            if c != '\n' {
+                value.push(c);
+            }
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c as u32 == quote as u32 {
+            return Some((value, position + 1 + offset + 1));
+        } else if c == '\n' {
+            return None;
+        } else {
+            value.push(c);
+        }
+    }
+    return None;
+}
+
+// reads what's in a url(...) whose ( is just before `position`
+fn read_url(css: &str, position: usize) -> Option<(String, usize)> {
+    let bytes = css.as_bytes();
+    let position = skip_whitespace(bytes, position);
+
+    if position < bytes.len() && (bytes[position] == b'"' || bytes[position] == b'\'') {
+        let (value, end) = read_string(css, position)?;
+        let end = skip_whitespace(bytes, end);
+        if bytes.get(end) != Some(&b')') {
+            return None;
+        }
+        return Some((value, end + 1));
+    }
+
+    let mut value = String::new();
+    let mut escaped = false;
+    for (offset, c) in css[position..].char_indices() {
+        if escaped {
+            value.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ')' {
+            return Some((value.trim().to_string(), position + offset + 1));
+        } else {
+            value.push(c);
+        }
+    }
+    return None;
+}
+
+/// Finds the URLs a stylesheet refers to, in `url()`s and `@import`s, as
+/// they're written.
+pub fn find_urls_in_css(css: &str) -> Vec<(String, CssReference)> {
+    let bytes = css.as_bytes();
+    let mut urls = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+
+        let found = if rest.starts_with(b"/*") {
+            position = css[position + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |x| position + 2 + x + 2);
+            None
+        } else if rest[0] == b'"' || rest[0] == b'\'' {
+            // strings are only skipped, as content: "url(x)" isn't a URL
+            position = read_string(css, position).map_or(position + 1, |x| x.1);
+            None
+        } else if starts_with_ignore_case(rest, b"@import") {
+            let start = skip_whitespace(bytes, position + 7);
+            let found = if start < bytes.len() && (bytes[start] == b'"' || bytes[start] == b'\'') {
+                read_string(css, start)
+            } else if starts_with_ignore_case(&bytes[start..], b"url(") {
+                read_url(css, start + 4)
+            } else {
+                None
+            };
+            position = found.as_ref().map_or(position + 7, |x| x.1);
+            found.map(|x| (x.0, CssReference::Import))
+        } else if starts_with_ignore_case(rest, b"url(")
+            && (position == 0 || !(bytes[position - 1] as char).is_ascii_alphanumeric())
+        {
+            let found = read_url(css, position + 4);
+            position = found.as_ref().map_or(position + 4, |x| x.1);
+            found.map(|x| (x.0, CssReference::Url))
+        } else {
+            position += 1;
+            None
+        };
+
+        if let Some((url, reference)) = found {
+            // data: URLs are already there
+            if url.len() != 0 && !starts_with_ignore_case(url.as_bytes(), b"data:") {
+                urls.push((url, reference));
+            }
+        }
+    }
+
+    return urls;
+}
+
+/// Handles stylesheets, following their imports, fonts and images.
+pub struct CssHandler;
+
+impl ContentHandler for CssHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "text/css"
+    }
+
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult {
+        let (css, _) = charset::decode(body, &content_type(headers));
+
+        let mut result = CrawlResult::new("css");
+        // a stylesheet is only worth crawling for what it links to
+        result.index = false;
+        for (found, reference) in find_urls_in_css(&css) {
+            let joined = match url.join(&found) {
+                Ok(joined) => joined.to_string(),
+                Err(error) => {
+                    debug!("bad URL {:?} in {}: {}", found, url, error);
+                    continue;
+                }
+            };
+
+            if !result.links.iter().any(|x| x.url == joined) {
+                let mut link = Link::new(joined);
+                link.element = "css".to_string();
+                link.attribute = reference.as_str().to_string();
+                result.links.push(link);
+            }
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use css::*;
+
+    #[test]
+    fn _find_urls_in_css() {
+        fn urls(css: &str) -> Vec<String> {
+            find_urls_in_css(css)
+                .into_iter()
+                .map(|(url, reference)| format!("{} {}", reference.as_str(), url))
+                .collect()
+        }
+
+        assert_eq!(
+            urls(
+                "@import 'base.css';\n@IMPORT url(\"print.css\") print;\n\
+                 body { background: URL( bg.png ) no-repeat; }\n\
+                 /* .old { background: url(old.png) } */\n\
+                 @font-face { src: url('fonts/a\\'b.woff2') format('woff2'), url(data:font/woff;base64,AAAA); }\n\
+                 .quote::before { content: 'url(not-a-url.png)'; }\n\
+                 .icon { background-image: url(icons/a\\(1\\).svg), myurl(x.png) }"
+            ),
+            vec![
+                "import base.css",
+                "import print.css",
+                "url bg.png",
+                "url fonts/a'b.woff2",
+                "url icons/a(1).svg",
+            ]
+        );
+        assert_eq!(
+            urls("a { background: url(unclosed.png"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn _css_handler() {
+        let url = Url::parse("https://google.com/css/main.css").unwrap();
+        let result = CssHandler.process(
+            &url,
+            &Headers::new(),
+            b"@import 'reset.css'; h1 { background: url(/img/h1.png) } \
+              h2 { background: url(/img/h1.png) }",
+        );
+
+        assert_eq!(result.kind, "css");
+        assert!(!result.index);
+        assert_eq!(
+            result.links,
+            vec![
+                Link {
+                    element: "css".to_string(),
+                    attribute: "import".to_string(),
+                    ..Link::new("https://google.com/css/reset.css".to_string())
+                },
+                Link {
+                    element: "css".to_string(),
+                    attribute: "url".to_string(),
+                    ..Link::new("https://google.com/img/h1.png".to_string())
+                },
+            ]
+        );
+    }
+}
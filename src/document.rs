@@ -0,0 +1,272 @@
+use feed::FeedEntry;
+use html5ever::tokenizer::{EndTag, StartTag, Tag};
+use link::Link;
+use url::Url;
+
+// elements whose contents aren't part of the text of the page
+static SKIPPED_ELEMENTS: [&str; 6] = ["head", "script", "style", "noscript", "template", "title"];
+
+// elements that separate words, unlike <b> or <span>
+static BLOCK_ELEMENTS: [&str; 37] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Heading {
+    /// 1 for `<h1>` through 6 for `<h6>`.
+    pub level: u8,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct PageDocument {
+    pub title: String,
+    pub description: String,
+    /// The `<link rel="canonical">`, resolved against the page's URL.
+    pub canonical: String,
+    /// The `lang` of the `<html>` element.
+    pub lang: String,
+    pub headings: Vec<Heading>,
+    /// The text of the page's `<main>`, or of the whole page if it has none.
+    pub text: String,
+    pub word_count: usize,
+}
+
+/// Where a page sends its visitors, with a `<meta http-equiv="refresh">` or
+/// a script, instead of an HTTP redirect.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageRedirect {
+    /// As written, resolved against the page's URL.
+    pub url: String,
+    /// In seconds; scripts redirect straight away.
+    pub delay: u32,
+}
+
+/// What came out of crawling a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrawlResult {
+    /// Whether the page may be indexed, according to its robots `<meta>` and X-Robots-Tag.
+    pub index: bool,
+    pub links: Vec<Link>,
+    /// What kind of document was crawled, such as "html".
+    pub kind: String,
+    pub meta: Vec<(String, String)>,
+    pub document: Option<PageDocument>,
+    /// The items of a feed.
+    pub entries: Vec<FeedEntry>,
+    pub redirect: Option<PageRedirect>,
+}
+
+impl CrawlResult {
+    pub fn new(kind: &str) -> CrawlResult {
+        CrawlResult {
+            index: true,
+            links: Vec::new(),
+            kind: kind.to_string(),
+            meta: Vec::new(),
+            document: None,
+            entries: Vec::new(),
+            redirect: None,
+        }
+    }
+}
+
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Builds a `PageDocument` from the tokens of a page, as they're tokenized.
+#[derive(Debug, Default)]
+pub struct DocumentBuilder {
+    document: PageDocument,
+    skip_depth: usize,
+    main_depth: usize,
+    in_title: bool,
+    heading: Option<Heading>,
+    text: String,
+    main_text: String,
+}
+
+impl DocumentBuilder {
+    pub fn new() -> DocumentBuilder {
+        DocumentBuilder::default()
+    }
+
+    pub fn process_tag(&mut self, tag: &Tag, base: &Url) {
+        let attribute = |name: &str| {
+            tag.attrs
+                .iter()
+                .find(|x| &x.name.local == name)
+                .map(|x| x.value.to_string())
+        };
+
+        match (tag.kind, &*tag.name) {
+            (StartTag, "html") => {
+                self.document.lang = attribute("lang")
+                    .unwrap_or(String::new())
+                    .trim()
+                    .to_string();
+            }
+            (StartTag, "title") => {
+                self.in_title = self.document.title.len() == 0;
+            }
+            (EndTag, "title") => {
+                self.in_title = false;
+            }
+            (StartTag, "meta") => {
+                let name = attribute("name").unwrap_or(String::new()).to_lowercase();
+                if name == "description" && self.document.description.len() == 0 {
+                    self.document.description =
+                        collapse_whitespace(&attribute("content").unwrap_or(String::new()));
+                }
+            }
+            (StartTag, "link") => {
+                let rel = attribute("rel").unwrap_or(String::new()).to_lowercase();
+                if rel.split_whitespace().any(|x| x == "canonical") {
+                    if let Some(canonical) =
+                        attribute("href").and_then(|x| base.join(x.trim()).ok())
+                    {
+                        self.document.canonical = canonical.as_str().to_string();
+                    }
+                }
+            }
+            (StartTag, "main") => self.main_depth += 1,
+            (EndTag, "main") if self.main_depth > 0 => self.main_depth -= 1,
+            // <body> implies </head>, for pages that never close it
+            (StartTag, "body") => self.skip_depth = 0,
+            _ => {}
+        }
+
+        if let Some(level) = heading_level(&tag.name) {
+            if tag.kind == EndTag {
+                if let Some(heading) = self.heading.take() {
+                    let text = collapse_whitespace(&heading.text);
+                    if text.len() != 0 {
+                        self.document.headings.push(Heading {
+                            level: heading.level,
+                            text: text,
+                        });
+                    }
+                }
+            } else {
+                self.heading = Some(Heading {
+                    level: level,
+                    text: String::new(),
+                });
+            }
+        }
+
+        if SKIPPED_ELEMENTS.iter().any(|x| *x == &*tag.name) && !tag.self_closing {
+            if tag.kind == StartTag {
+                self.skip_depth += 1;
+            } else if self.skip_depth > 0 {
+                self.skip_depth -= 1;
+            }
+        }
+
+        if BLOCK_ELEMENTS.iter().any(|x| *x == &*tag.name) {
+            self.push_text(" ");
+        }
+    }
+
+    pub fn process_text(&mut self, text: &str) {
+        if self.in_title {
+            self.document.title.push_str(text);
+        }
+
+        if self.skip_depth == 0 {
+            self.push_text(text);
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+        if self.main_depth > 0 {
+            self.main_text.push_str(text);
+        }
+        if let Some(ref mut heading) = self.heading {
+            heading.text.push_str(text);
+        }
+    }
+
+    pub fn finish(self) -> PageDocument {
+        let mut document = self.document;
+
+        document.title = collapse_whitespace(&document.title);
+        document.text = collapse_whitespace(if self.main_text.trim().len() != 0 {
+            &self.main_text
+        } else {
+            &self.text
+        });
+        document.word_count = document.text.split_whitespace().count();
+
+        return document;
+    }
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use document::*;
+
+    #[test]
+    fn _collapse_whitespace() {
+        assert_eq!(collapse_whitespace("\n a \t b\r\n\nc  "), "a b c");
+        assert_eq!(collapse_whitespace("   "), "");
+    }
+
+    #[test]
+    fn _heading_level() {
+        assert_eq!(heading_level("h1"), Some(1));
+        assert_eq!(heading_level("h6"), Some(6));
+        assert_eq!(heading_level("h7"), None);
+        assert_eq!(heading_level("header"), None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// How much of an HTML page is read before the rest is dropped.
+pub static DEFAULT_MAX_HTML_BYTES: u64 = 10_000_000;
+/// The same, for everything else (images, archives...), unless its handler
+/// asks for more.
+pub static DEFAULT_MAX_OTHER_BYTES: u64 = 2_000_000;
+/// How long a body can take to download.
+pub static DEFAULT_MAX_SECONDS: u64 = 60;
+
+static CHUNK_SIZE: usize = 8192;
+
+/// Why a body was cut short, named like WARC's `WARC-Truncated` values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Truncation {
+    Length,
+    Time,
+}
+
+impl Truncation {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Truncation::Length => "length",
+            Truncation::Time => "time",
+        }
+    }
+}
+
+/// Reads a body, stopping after `max_bytes` or once `max_time` has passed.
+/// The time is only checked between reads, so a server that stops sending
+/// altogether needs a timeout on the connection as well.
+pub fn read_body<R: Read>(
+    reader: &mut R,
+    max_bytes: u64,
+    max_time: Duration,
+) -> io::Result<(Vec<u8>, Option<Truncation>)> {
+    read_body_with(reader, max_bytes, max_time, |_| {})
+}
+
+/// Like `read_body`, but also hands each chunk to `on_chunk` as it arrives.
+pub fn read_body_with<R: Read, F: FnMut(&[u8])>(
+    reader: &mut R,
+    max_bytes: u64,
+    max_time: Duration,
+    mut on_chunk: F,
+) -> io::Result<(Vec<u8>, Option<Truncation>)> {
+    let started = Instant::now();
+    let mut body = Vec::new();
+    let mut chunk = [0; CHUNK_SIZE];
+
+    loop {
+        if body.len() as u64 >= max_bytes {
+            // one more byte tells a body that's exactly max_bytes from a longer one
+            let mut byte = [0];
+            if reader.read(&mut byte)? == 0 {
+                return Ok((body, None));
+            }
+            return Ok((body, Some(Truncation::Length)));
+        }
+        if started.elapsed() >= max_time {
+            return Ok((body, Some(Truncation::Time)));
+        }
+
+        let wanted = (max_bytes - body.len() as u64).min(CHUNK_SIZE as u64) as usize;
+        match reader.read(&mut chunk[..wanted]) {
+            Ok(0) => return Ok((body, None)),
+            Ok(read) => {
+                on_chunk(&chunk[..read]);
+                body.extend_from_slice(&chunk[..read]);
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use download::*;
+
+    // gives out a byte at a time, slowly
+    struct SlowReader(usize);
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            ::std::thread::sleep(Duration::from_millis(10));
+            if self.0 == 0 || buf.len() == 0 {
+                return Ok(0);
+            }
+            self.0 -= 1;
+            buf[0] = b'a';
+            return Ok(1);
+        }
+    }
+
+    #[test]
+    fn _read_body() {
+        let body = vec![b'a'; 20000];
+        let long = Duration::from_secs(60);
+
+        assert_eq!(
+            read_body(&mut &body[..], 30000, long).unwrap(),
+            (body.clone(), None)
+        );
+        assert_eq!(
+            read_body(&mut &body[..], 20000, long).unwrap(),
+            (body.clone(), None)
+        );
+        assert_eq!(
+            read_body(&mut &body[..], 10000, long).unwrap(),
+            (body[..10000].to_vec(), Some(Truncation::Length))
+        );
+
+        let (body, truncation) =
+            read_body(&mut SlowReader(1000), 1000, Duration::from_millis(50)).unwrap();
+        assert!(body.len() < 1000);
+        assert_eq!(truncation, Some(Truncation::Time));
+        assert_eq!(Truncation::Time.as_str(), "time");
+
+        let mut chunks = Vec::new();
+        let (body, _) = read_body_with(&mut &[b'a'; 20000][..], 10000, long, |x| {
+            chunks.extend_from_slice(x)
+        })
+        .unwrap();
+        assert_eq!(chunks, body);
+    }
+}
@@ -0,0 +1,392 @@
+use chrono::{DateTime, Utc};
+use charset;
+use document::{collapse_whitespace, CrawlResult};
+use handler::{content_type, ContentHandler};
+use link::Link;
+use options::Format;
+use output::{escape_field, PageRecord};
+use reqwest::header::Headers;
+use serde_json;
+use sink::{Finish, OutputSink};
+use sitemap::{self, SitemapHandler};
+use std::io::{self, Write};
+use url::Url;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Media types feeds are served as. Plain XML is also checked for a feed.
+pub static FEED_TYPES: [&str; 3] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/rdf+xml",
+];
+
+/// An RSS `<item>` or Atom `<entry>`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct FeedEntry {
+    /// The URL of the feed the entry is in.
+    pub feed: String,
+    pub url: String,
+    pub title: String,
+    /// When the entry was published (or failing that, updated), in RFC 3339.
+    /// Empty if it doesn't say or the date couldn't be read.
+    pub published: String,
+    pub author: String,
+}
+
+impl FeedEntry {
+    pub fn to_tsv(&self) -> String {
+        [
+            &self.feed,
+            &self.url,
+            &self.title,
+            &self.published,
+            &self.author,
+        ]
+        .iter()
+        .map(|x| escape_field(x))
+        .collect::<Vec<String>>()
+        .join("\t")
+    }
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|x| x.name.local_name == name)
+        .map(|x| x.value.as_str())
+}
+
+/// Reads the dates feeds use: RFC 822 ones in RSS, and W3C ones in Atom and
+/// Dublin Core.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(|x| x.with_timezone(&Utc))
+        .or_else(|| sitemap::parse_lastmod(date))
+}
+
+/// Parses an RSS (0.9x, 1.0 or 2.0) or Atom feed into its entries, with
+/// their URLs as they're written. Returns `None` if it isn't a feed.
+pub fn parse_feed(xml: &str) -> Option<Vec<FeedEntry>> {
+    let mut is_feed = None;
+    let mut entries = Vec::new();
+    let mut entry: Option<FeedEntry> = None;
+    // the guid or rdf:about, in case there's no <link>
+    let mut fallback_url = String::new();
+    let mut updated = String::new();
+    let mut elements: Vec<String> = Vec::new();
+    let mut text = String::new();
+
+    for event in EventReader::new(xml.as_bytes()) {
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                if is_feed == None {
+                    match name.local_name.as_str() {
+                        "rss" | "feed" | "RDF" => is_feed = Some(true),
+                        _ => {
+                            debug!("not a feed (root element {})", name.local_name);
+                            return None;
+                        }
+                    }
+                }
+
+                let mut element = name.local_name;
+                match element.as_str() {
+                    "item" | "entry" => {
+                        entry = Some(FeedEntry::default());
+                        fallback_url = attribute(&attributes, "about").unwrap_or("").to_string();
+                        updated = String::new();
+                    }
+                    // Atom links are attributes, and only the alternate one is the entry itself
+                    "link" => {
+                        if let (Some(entry), Some(href)) =
+                            (entry.as_mut(), attribute(&attributes, "href"))
+                        {
+                            let rel = attribute(&attributes, "rel").unwrap_or("alternate");
+                            if rel == "alternate" && entry.url.len() == 0 {
+                                entry.url = href.trim().to_string();
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                // a guid that isn't a permalink is just an ID, not a URL
+                if element == "guid" && attribute(&attributes, "isPermaLink") == Some("false") {
+                    element = "id".to_string();
+                }
+                elements.push(element);
+                text = String::new();
+            }
+            Ok(XmlEvent::Characters(characters)) | Ok(XmlEvent::CData(characters)) => {
+                text.push_str(&characters);
+            }
+            Ok(XmlEvent::EndElement { .. }) => {
+                let element = elements.pop().unwrap_or(String::new());
+                let parent = elements.last().map(|x| x.as_str()).unwrap_or("");
+                let value = collapse_whitespace(&text);
+                text = String::new();
+
+                if let Some(ref mut entry) = entry {
+                    match (element.as_str(), parent) {
+                        ("link", "item") if entry.url.len() == 0 => entry.url = value,
+                        ("guid", "item") => fallback_url = value,
+                        ("title", "item") | ("title", "entry") => entry.title = value,
+                        ("pubDate", _) | ("published", _) | ("issued", _) | ("date", "item") => {
+                            if let Some(date) = parse_date(&value) {
+                                entry.published = date.to_rfc3339();
+                            }
+                        }
+                        ("updated", "entry") | ("modified", "entry") => updated = value,
+                        ("name", "author") | ("creator", "item") => entry.author = value,
+                        ("author", "item") if entry.author.len() == 0 => entry.author = value,
+                        _ => {}
+                    }
+                }
+
+                if element == "item" || element == "entry" {
+                    if let Some(mut done) = entry.take() {
+                        if done.url.len() == 0 {
+                            done.url = fallback_url.clone();
+                        }
+                        if done.published.len() == 0 {
+                            if let Some(date) = parse_date(&updated) {
+                                done.published = date.to_rfc3339();
+                            }
+                        }
+                        if done.url.len() != 0 {
+                            entries.push(done);
+                        }
+                    }
+                }
+            }
+            Err(error) => {
+                // feeds are often cut short or invalid, so keep what was read
+                warn!("error parsing feed: {}", error);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    is_feed.map(|_| entries)
+}
+
+/// Handles RSS and Atom feeds, following each entry's link. Anything served
+/// as plain XML that isn't a feed is handed on to `SitemapHandler`.
+pub struct FeedHandler;
+
+impl ContentHandler for FeedHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        FEED_TYPES.contains(&mime) || mime == "application/xml" || mime == "text/xml"
+    }
+
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult {
+        let (text, _) = charset::decode(body, &content_type(headers));
+        let entries = match parse_feed(&text) {
+            Some(entries) => entries,
+            None => return SitemapHandler.process(url, headers, body),
+        };
+
+        let mut result = CrawlResult::new("feed");
+        // entries are written out on their own instead
+        result.index = false;
+        for mut entry in entries {
+            entry.feed = url.to_string();
+            entry.url = match url.join(&entry.url) {
+                Ok(joined) => joined.to_string(),
+                Err(error) => {
+                    debug!("bad URL {:?} in feed {}: {}", entry.url, url, error);
+                    continue;
+                }
+            };
+
+            if !result.links.iter().any(|x| x.url == entry.url) {
+                let mut link = Link::new(entry.url.clone());
+                link.element = "item".to_string();
+                link.attribute = "link".to_string();
+                link.title = entry.title.clone();
+                result.links.push(link);
+            }
+            result.entries.push(entry);
+        }
+        return result;
+    }
+}
+
+/// Writes one line per feed entry.
+pub struct EntryWriter<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl<W: Write> EntryWriter<W> {
+    pub fn new(writer: W, format: Format) -> EntryWriter<W> {
+        EntryWriter {
+            writer: writer,
+            format: format,
+        }
+    }
+}
+
+impl<W: Finish> OutputSink for EntryWriter<W> {
+    fn on_page(&mut self, _page: &PageRecord, _body: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_entry(&mut self, entry: &FeedEntry) -> io::Result<()> {
+        match self.format {
+            Format::Tsv => writeln!(self.writer, "{}", entry.to_tsv()),
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.writer, entry)?;
+                self.writer.write_all(b"\n")
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use feed::*;
+
+    #[test]
+    fn _parse_date() {
+        assert_eq!(
+            parse_date("Tue, 01 May 2018 12:00:00 GMT").map(|x| x.to_rfc3339()),
+            Some("2018-05-01T12:00:00+00:00".to_string())
+        );
+        assert_eq!(
+            parse_date("2018-05-01T14:00:00+02:00").map(|x| x.to_rfc3339()),
+            Some("2018-05-01T12:00:00+00:00".to_string())
+        );
+        assert_eq!(parse_date("yesterday"), None);
+    }
+
+    #[test]
+    fn _parse_feed() {
+        assert_eq!(
+            parse_feed(
+                "<?xml version='1.0'?>\
+                 <rss version='2.0' xmlns:dc='http://purl.org/dc/elements/1.1/'><channel>\
+                 <title>News</title><link>https://google.com/</link>\
+                 <item><title>First  story</title><link>https://google.com/1</link>\
+                 <pubDate>Tue, 01 May 2018 12:00:00 GMT</pubDate><dc:creator>Ann</dc:creator></item>\
+                 <item><title><![CDATA[Second <b>story</b>]]></title>\
+                 <guid>https://google.com/2</guid><author>bob@google.com (Bob)</author></item>\
+                 <item><title>No link</title><guid isPermaLink='false'>abc</guid></item>\
+                 </channel></rss>"
+            ),
+            Some(vec![
+                FeedEntry {
+                    url: "https://google.com/1".to_string(),
+                    title: "First story".to_string(),
+                    published: "2018-05-01T12:00:00+00:00".to_string(),
+                    author: "Ann".to_string(),
+                    ..FeedEntry::default()
+                },
+                FeedEntry {
+                    url: "https://google.com/2".to_string(),
+                    title: "Second <b>story</b>".to_string(),
+                    author: "bob@google.com (Bob)".to_string(),
+                    ..FeedEntry::default()
+                },
+            ])
+        );
+
+        assert_eq!(
+            parse_feed(
+                "<feed xmlns='http://www.w3.org/2005/Atom'><title>News</title>\
+                 <link href='https://google.com/'/>\
+                 <entry><title>Story</title>\
+                 <link rel='edit' href='/edit/1'/><link href='/1'/>\
+                 <updated>2018-05-02T12:00:00Z</updated>\
+                 <author><name>Ann</name></author></entry></feed>"
+            ),
+            Some(vec![FeedEntry {
+                url: "/1".to_string(),
+                title: "Story".to_string(),
+                published: "2018-05-02T12:00:00+00:00".to_string(),
+                author: "Ann".to_string(),
+                ..FeedEntry::default()
+            }])
+        );
+
+        assert_eq!(
+            parse_feed(
+                "<rdf:RDF xmlns:rdf='http://www.w3.org/1999/02/22-rdf-syntax-ns#' \
+                 xmlns='http://purl.org/rss/1.0/'>\
+                 <item rdf:about='https://google.com/1'><title>Story</title></item></rdf:RDF>"
+            )
+            .unwrap()[0]
+                .url,
+            "https://google.com/1"
+        );
+
+        assert_eq!(parse_feed("<urlset></urlset>"), None);
+    }
+
+    #[test]
+    fn _feed_handler() {
+        let url = Url::parse("https://google.com/news/feed").unwrap();
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", "application/atom+xml");
+
+        let result = FeedHandler.process(
+            &url,
+            &headers,
+            b"<feed xmlns='http://www.w3.org/2005/Atom'>\
+              <entry><title>Story</title><link href='1'/></entry>\
+              <entry><title>Again</title><link href='1'/></entry></feed>",
+        );
+        assert_eq!(result.kind, "feed");
+        assert!(!result.index);
+        assert_eq!(result.links.len(), 1);
+        assert_eq!(result.links[0].url, "https://google.com/news/1");
+        assert_eq!(result.links[0].title, "Story");
+        assert_eq!(result.entries.len(), 2);
+        assert_eq!(result.entries[0].feed, "https://google.com/news/feed");
+
+        // plain XML is only a feed if it looks like one
+        headers.set_raw("Content-Type", "text/xml");
+        let result = FeedHandler.process(
+            &url,
+            &headers,
+            b"<urlset><url><loc>https://google.com/news</loc></url></urlset>",
+        );
+        assert_eq!(result.kind, "sitemap");
+    }
+
+    #[test]
+    fn _entry_writer() {
+        let entry = FeedEntry {
+            feed: "https://google.com/feed".to_string(),
+            url: "https://google.com/1".to_string(),
+            title: "A\tstory".to_string(),
+            ..FeedEntry::default()
+        };
+
+        let mut writer = EntryWriter::new(Vec::new(), Format::Tsv);
+        writer.on_entry(&entry).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.writer).unwrap(),
+            "https://google.com/feed\thttps://google.com/1\tA\\tstory\t\t\n"
+        );
+
+        let mut writer = EntryWriter::new(Vec::new(), Format::JsonLines);
+        writer.on_entry(&entry).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&writer.writer).unwrap();
+        assert_eq!(value["url"], "https://google.com/1");
+        assert_eq!(value["title"], "A\tstory");
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+pub static PRIORITY_LOW: u32 = 100;
+pub static PRIORITY_NORMAL: u32 = 500;
+/// For feeds and what they list, which are likely new.
+pub static PRIORITY_HIGH: u32 = 900;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontierEntry {
+    pub url: String,
+    pub priority: u32,
+    pub depth: u32,
+    /// Only check that the URL exists (with a HEAD request) instead of crawling it.
+    pub head_only: bool,
+    sequence: u64,
+}
+
+impl Ord for FrontierEntry {
+    // BinaryHeap pops the greatest entry: highest priority first, then the
+    // shallowest, then whichever was pushed first (so equal entries stay breadth-first)
+    fn cmp(&self, other: &FrontierEntry) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then(other.depth.cmp(&self.depth))
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for FrontierEntry {
+    fn partial_cmp(&self, other: &FrontierEntry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The URLs waiting to be crawled. Each is only queued once, even after it's
+/// been popped, though one that was only going to be checked can still be
+/// queued to be crawled.
+#[derive(Debug, Default)]
+pub struct Frontier {
+    heap: BinaryHeap<FrontierEntry>,
+    sequence: u64,
+    queued: HashSet<String>,
+    checked: HashSet<String>,
+}
+
+impl Frontier {
+    pub fn new() -> Frontier {
+        Frontier::default()
+    }
+
+    pub fn push(&mut self, url: String, priority: u32, depth: u32) {
+        self.push_entry(url, priority, depth, false);
+    }
+
+    pub fn push_head_only(&mut self, url: String, priority: u32, depth: u32) {
+        self.push_entry(url, priority, depth, true);
+    }
+
+    fn push_entry(&mut self, url: String, priority: u32, depth: u32, head_only: bool) {
+        if self.queued.contains(&url) || (head_only && self.checked.contains(&url)) {
+            trace!("already queued {}", url);
+            return;
+        }
+        if head_only {
+            self.checked.insert(url.clone());
+        } else {
+            self.queued.insert(url.clone());
+        }
+
+        trace!("queueing {} (priority {}, depth {})", url, priority, depth);
+        self.sequence += 1;
+        self.heap.push(FrontierEntry {
+            url: url,
+            priority: priority,
+            depth: depth,
+            head_only: head_only,
+            sequence: self.sequence,
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<FrontierEntry> {
+        self.heap.pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontier::*;
+
+    #[test]
+    fn _frontier() {
+        let mut frontier = Frontier::new();
+        assert_eq!(frontier.pop(), None);
+
+        frontier.push("https://google.com/deep".to_string(), PRIORITY_NORMAL, 1);
+        frontier.push("https://google.com/low".to_string(), PRIORITY_LOW, 0);
+        frontier.push("https://google.com/a".to_string(), PRIORITY_NORMAL, 0);
+        frontier.push_head_only("https://google.com/b".to_string(), PRIORITY_NORMAL, 0);
+        assert_eq!(frontier.len(), 4);
+
+        let entries: Vec<FrontierEntry> = (0..4).map(|_| frontier.pop().unwrap()).collect();
+        assert_eq!(
+            entries.iter().map(|x| x.head_only).collect::<Vec<bool>>(),
+            vec![false, true, false, false]
+        );
+
+        let order: Vec<String> = entries.into_iter().map(|x| x.url).collect();
+        assert_eq!(
+            order,
+            vec![
+                "https://google.com/a".to_string(),
+                "https://google.com/b".to_string(),
+                "https://google.com/deep".to_string(),
+                "https://google.com/low".to_string(),
+            ]
+        );
+        assert_eq!(frontier.len(), 0);
+    }
+
+    #[test]
+    fn _frontier_duplicates() {
+        let mut frontier = Frontier::new();
+        frontier.push("https://google.com/a".to_string(), PRIORITY_NORMAL, 0);
+        frontier.push("https://google.com/a".to_string(), PRIORITY_HIGH, 0);
+        frontier.push_head_only("https://google.com/a".to_string(), PRIORITY_NORMAL, 0);
+        frontier.push_head_only("https://google.com/b".to_string(), PRIORITY_NORMAL, 0);
+        frontier.push_head_only("https://google.com/b".to_string(), PRIORITY_NORMAL, 0);
+        assert_eq!(frontier.len(), 2);
+
+        // popped URLs aren't queued again, but a checked one can still be crawled
+        while frontier.pop().is_some() {}
+        frontier.push("https://google.com/a".to_string(), PRIORITY_NORMAL, 1);
+        frontier.push_head_only("https://google.com/b".to_string(), PRIORITY_NORMAL, 1);
+        assert_eq!(frontier.len(), 0);
+        frontier.push("https://google.com/b".to_string(), PRIORITY_NORMAL, 1);
+        assert_eq!(
+            frontier.pop().map(|x| (x.url, x.head_only)),
+            Some(("https://google.com/b".to_string(), false))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use css::CssHandler;
+use document::CrawlResult;
+use feed::FeedHandler;
+use html::HtmlHandler;
+use mime_sniff;
+use pdf::{self, PdfHandler};
+use reqwest::header::{ContentType, Headers};
+use sitemap::SitemapHandler;
+use text::{MarkdownHandler, TextHandler};
+use url::Url;
+
+/// The Content-Type header, or an empty string if there isn't one.
+pub fn content_type(headers: &Headers) -> String {
+    headers
+        .get::<ContentType>()
+        .map(|x| x.to_string())
+        .unwrap_or(String::new())
+}
+
+/// Pulls links and text out of one kind of document.
+pub trait ContentHandler {
+    /// Whether this handler understands bodies of a media type, given
+    /// lowercased and without parameters, like `text/html`.
+    fn accepts(&self, mime: &str) -> bool;
+
+    /// Processes a body. Its Content-Type header is what the body was sniffed
+    /// as, which isn't always what the server said.
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult;
+
+    /// Starts on a body that's still downloading, for handlers that can read
+    /// one a chunk at a time. The rest are given the whole body by `process`.
+    fn start(&self, _url: &Url, _headers: &Headers) -> Option<Box<dyn BodyParser>> {
+        None
+    }
+
+    /// How much of a body to download, for handlers that need a different
+    /// limit than the crawl's `--max-html-bytes` or `--max-other-bytes`.
+    fn max_bytes(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Reads a body as it arrives, see `ContentHandler::start`.
+pub trait BodyParser {
+    fn feed(&mut self, chunk: &[u8]);
+
+    fn finish(self: Box<Self>) -> CrawlResult;
+}
+
+/// The handlers a crawl uses. Ones registered later are tried first, so they
+/// can take over types the built-in ones would otherwise get.
+#[derive(Default)]
+pub struct Handlers {
+    handlers: Vec<Box<dyn ContentHandler>>,
+}
+
+impl Handlers {
+    pub fn new() -> Handlers {
+        Handlers::default()
+    }
+
+    /// The handlers that come with the crawler.
+    pub fn builtin() -> Handlers {
+        let mut handlers = Handlers::new();
+        handlers.register(Box::new(HtmlHandler));
+        handlers.register(Box::new(SitemapHandler));
+        // feeds are often served as plain XML, like sitemaps
+        handlers.register(Box::new(FeedHandler));
+        handlers.register(Box::new(PdfHandler::default()));
+        handlers.register(Box::new(TextHandler));
+        handlers.register(Box::new(MarkdownHandler));
+        handlers.register(Box::new(CssHandler));
+        return handlers;
+    }
+
+    pub fn register(&mut self, handler: Box<dyn ContentHandler>) {
+        self.handlers.push(handler);
+    }
+
+    pub fn find(&self, mime: &str) -> Option<&dyn ContentHandler> {
+        self.handlers
+            .iter()
+            .rev()
+            .find(|x| x.accepts(mime))
+            .map(|x| &**x)
+    }
+
+    /// Starts reading a body with whichever handler accepts its Content-Type, if
+    /// that handler can read it before it's all there.
+    pub fn start(&self, url: &Url, headers: &Headers) -> Option<Box<dyn BodyParser>> {
+        let mime = mime_sniff::essence(&content_type(headers));
+        self.find(&mime).and_then(|x| x.start(url, headers))
+    }
+
+    /// The download limit of whichever handler accepts a Content-Type, if it has one.
+    pub fn max_bytes(&self, headers: &Headers) -> Option<u64> {
+        let mime = mime_sniff::essence(&content_type(headers));
+        self.find(&mime).and_then(|x| x.max_bytes())
+    }
+
+    /// Hands a body to whichever handler accepts its Content-Type, if any does.
+    pub fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> Option<CrawlResult> {
+        let mime = mime_sniff::essence(&content_type(headers));
+
+        match self.find(&mime) {
+            Some(handler) => Some(handler.process(url, headers, body)),
+            None => {
+                debug!("nothing handles {} ({:?})", url, mime);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use handler::*;
+
+    // claims everything starting with a prefix, and says which it was
+    struct TestHandler(&'static str, &'static str);
+
+    impl ContentHandler for TestHandler {
+        fn accepts(&self, mime: &str) -> bool {
+            mime.starts_with(self.0)
+        }
+
+        fn process(&self, _url: &Url, _headers: &Headers, _body: &[u8]) -> CrawlResult {
+            CrawlResult::new(self.1)
+        }
+    }
+
+    #[test]
+    fn _handlers() {
+        let url = Url::parse("https://google.com/").unwrap();
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", "Text/HTML; charset=utf-8");
+
+        let mut handlers = Handlers::new();
+        assert_eq!(handlers.process(&url, &headers, b""), None);
+
+        handlers.register(Box::new(TestHandler("text/", "text")));
+        assert_eq!(handlers.process(&url, &headers, b"").unwrap().kind, "text");
+
+        handlers.register(Box::new(TestHandler("text/html", "html")));
+        assert_eq!(handlers.process(&url, &headers, b"").unwrap().kind, "html");
+        assert!(handlers.find("text/plain").is_some());
+        assert!(handlers.find("application/pdf").is_none());
+
+        assert_eq!(handlers.process(&url, &Headers::new(), b""), None);
+        assert_eq!(handlers.max_bytes(&headers), None);
+    }
+
+    #[test]
+    fn _builtin() {
+        let handlers = Handlers::builtin();
+        let url = Url::parse("https://google.com/").unwrap();
+        let mut headers = Headers::new();
+
+        headers.set_raw("Content-Type", "text/html; charset=Shift_JIS");
+        let result = handlers
+            .process(
+                &url,
+                &headers,
+                b"<title>\x83\x6a\x83\x85\x81\x5b\x83\x58</title>",
+            )
+            .unwrap();
+        assert_eq!(result.kind, "html");
+        assert_eq!(result.document.unwrap().title, "ニュース");
+
+        // HTML can be read as it downloads, split anywhere
+        let mut parser = handlers.start(&url, &headers).unwrap();
+        parser.feed(b"<title>\x83\x6a\x83");
+        parser.feed(b"\x85\x81\x5b\x83\x58</title>");
+        assert_eq!(parser.finish().document.unwrap().title, "ニュース");
+
+        headers.set_raw("Content-Type", "application/xml");
+        let result = handlers
+            .process(
+                &url,
+                &headers,
+                b"<urlset xmlns='http://www.sitemaps.org/schemas/sitemap/0.9'>\
+                  <url><loc>https://google.com/news</loc></url></urlset>",
+            )
+            .unwrap();
+        assert_eq!(result.kind, "sitemap");
+        assert_eq!(result.links[0].url, "https://google.com/news");
+
+        assert!(handlers.start(&url, &headers).is_none());
+        assert_eq!(handlers.max_bytes(&headers), None);
+
+        // PDFs can't be read without their end, so they get more
+        headers.set_raw("Content-Type", "application/pdf");
+        assert_eq!(handlers.max_bytes(&headers), Some(pdf::DEFAULT_MAX_BYTES));
+
+        assert!(handlers.find("image/png").is_none());
+    }
+}
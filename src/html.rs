@@ -1,184 +1,659 @@
 use url::Url;
 use html5ever::tokenizer::*;
-use html5ever::tendril::{ByteTendril, Tendril};
+use html5ever::tendril::{StrTendril, Tendril};
 use url_utils::*;
-use std;
+use link::{parse_rel, Link};
+use robots_tag::RobotsDirectives;
+use document::{collapse_whitespace, CrawlResult, DocumentBuilder, PageRedirect};
+use html5ever::tokenizer::states::RawKind;
+use encoding_rs::{Decoder, Encoding};
+use charset;
+use css::find_urls_in_css;
+use redirect::{parse_refresh, script_redirect};
+use handler::{content_type, BodyParser, ContentHandler};
+use reqwest::header::Headers;
 
-fn get_attribute_for_elem<'a>(elem: &str) -> Option<&'a str> {
-    match elem {
-        "a" => Some("href"),
-        "script" => Some("src"),
-        "link" => Some("href"),
-        "img" => Some("src"),
-        "iframe" => Some("src"),
-        "amp-img" => Some("src"),
-        "amp-anim" => Some("src"),
-        "amp-video" => Some("src"),
-        "amp-audio" => Some("src"),
-        "amp-iframe" => Some("src"),
-        _ => None,
+// elements that link to other URLs, and the attributes those URLs are in
+static LINK_ATTRIBUTES: [(&str, &[&str]); 21] = [
+    ("a", &["href"]),
+    ("area", &["href"]),
+    ("script", &["src"]),
+    ("link", &["href"]),
+    ("img", &["src", "srcset"]),
+    ("iframe", &["src"]),
+    ("source", &["src", "srcset"]),
+    ("embed", &["src"]),
+    ("object", &["data"]),
+    ("video", &["src", "poster"]),
+    ("audio", &["src"]),
+    ("track", &["src"]),
+    // only GET forms, see process_tag
+    ("form", &["action"]),
+    ("blockquote", &["cite"]),
+    ("q", &["cite"]),
+    ("amp-img", &["src", "srcset"]),
+    ("amp-anim", &["src", "srcset"]),
+    ("amp-video", &["src", "poster"]),
+    ("amp-audio", &["src"]),
+    ("amp-iframe", &["src"]),
+    ("amp-embed", &["src"]),
+];
+
+fn get_attributes_for_elem(elem: &str) -> &'static [&'static str] {
+    for entry in LINK_ATTRIBUTES.iter() {
+        if entry.0 == elem {
+            return entry.1;
+        }
     }
+
+    return &[];
 }
 
-struct HtmlTokenSink<'a>(&'a mut Vec<Token>);
+// anchor text and titles longer than this are cut off
+static MAX_LINK_TEXT_LENGTH: usize = 256;
+// how much of an anchor's text is kept before it's normalized, enough for
+// MAX_LINK_TEXT_LENGTH characters even with plenty of whitespace between them
+static MAX_ANCHOR_TEXT_BYTES: usize = 4096;
+// inline scripts longer than this do more than redirect, so aren't kept to be checked
+static MAX_SCRIPT_BYTES: usize = 1024;
+// how much of a <style> is scanned for URLs, so one that's never closed doesn't
+// hold on to the rest of the page
+static MAX_STYLE_BYTES: usize = 1_000_000;
 
-impl<'a> TokenSink for HtmlTokenSink<'a> {
-    type Handle = ();
+// appends as much of text as fits in max bytes
+fn push_capped(buffer: &mut String, text: &str, max: usize) {
+    if buffer.len() >= max {
+        return;
+    }
+    let mut end = text.len().min(max - buffer.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    buffer.push_str(&text[..end]);
+}
 
-    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
-        self.0.push(token);
-        return TokenSinkResult::Continue;
+/// Collapses runs of whitespace and caps the length of text describing a link.
+pub fn normalize_link_text(text: &str) -> String {
+    collapse_whitespace(text)
+        .chars()
+        .take(MAX_LINK_TEXT_LENGTH)
+        .collect()
+}
+
+// an <a> whose text is still being read
+struct OpenAnchor {
+    index: usize,
+    text: String,
+    image_alt: String,
+}
+
+fn close_anchor(anchor: &mut Option<OpenAnchor>, found_urls: &mut Vec<Link>) {
+    if let Some(anchor) = anchor.take() {
+        let link = &mut found_urls[anchor.index];
+        link.anchor_text = normalize_link_text(&anchor.text);
+        link.image_alt = normalize_link_text(&anchor.image_alt);
     }
 }
 
-pub fn find_urls_in_html(
-    original_url: Url,
-    raw_html: String,
+// extracts links, meta tags and text from tokens as the tokenizer produces them
+struct HtmlTokenSink {
+    url: Url,
     fetched_cache: Vec<String>,
-) -> Option<(bool, Vec<String>, String, Vec<(String, String)>)> {
-    let mut result = Vec::new();
-    let mut index_url = true;
-    let mut found_urls = Vec::new();
-    let mut meta: Vec<(String, String)> = Vec::new();
+    directives: RobotsDirectives,
+    links: Vec<Link>,
+    meta: Vec<(String, String)>,
+    anchor: Option<OpenAnchor>,
+    /// The contents of a `<style>` that hasn't been closed yet.
+    style: Option<String>,
+    /// The contents of an inline `<script>` that hasn't been closed yet.
+    script: Option<String>,
+    redirect: Option<PageRedirect>,
+    document: DocumentBuilder,
+}
 
-    {
-        let html = HtmlTokenSink(&mut result);
+impl HtmlTokenSink {
+    fn new(url: Url, fetched_cache: Vec<String>) -> HtmlTokenSink {
+        HtmlTokenSink {
+            url: url,
+            fetched_cache: fetched_cache,
+            directives: RobotsDirectives::default(),
+            links: Vec::new(),
+            meta: Vec::new(),
+            anchor: None,
+            style: None,
+            script: None,
+            redirect: None,
+            document: DocumentBuilder::new(),
+        }
+    }
 
-        let mut byte_tendril = ByteTendril::new();
-        {
-            let tendril_push_result = byte_tendril.try_push_bytes(&raw_html.into_bytes());
+    // adds the url()s and @imports of some CSS, found in `element`, resolved
+    // the way a browser would (as the stylesheet handler does)
+    fn add_css_links(&mut self, css: &str, element: &str, attribute: Option<&str>) {
+        for (found, reference) in find_urls_in_css(css) {
+            let joined = match self.url.join(&found) {
+                Ok(joined) => joined.to_string(),
+                Err(error) => {
+                    debug!("bad URL {:?} in {}: {}", found, self.url, error);
+                    continue;
+                }
+            };
 
-            if tendril_push_result.is_err() {
-                warn!("error pushing bytes to tendril: {:?}", tendril_push_result);
-                return None;
-            }
+            let mut link = Link::new(String::new());
+            link.element = element.to_string();
+            link.attribute = attribute.unwrap_or(reference.as_str()).to_string();
+
+            add_urls_to_vec(
+                Some(vec![joined]),
+                &link,
+                &mut self.links,
+                &self.fetched_cache,
+            );
         }
+    }
 
-        let mut queue = BufferQueue::new();
-        queue.push_back(byte_tendril.try_reinterpret().unwrap());
-        let mut tok = Tokenizer::new(html, std::default::Default::default()); // default default! default?
-        let _feed = tok.feed(&mut queue);
+    // follows a refresh or script redirect, keeping only the first as the page's
+    fn add_redirect(&mut self, found: &str, element: &str, attribute: &str, delay: u32) {
+        let urls = repair_suggested_url(&self.url, (attribute, found));
+        if self.redirect.is_none() {
+            if let Some(url) = urls.as_ref().and_then(|x| x.first()) {
+                self.redirect = Some(PageRedirect {
+                    url: url.clone(),
+                    delay: delay,
+                });
+            }
+        }
 
-        assert!(queue.is_empty());
-        tok.end();
+        let mut link = Link::new(String::new());
+        link.element = element.to_string();
+        link.attribute = attribute.to_string();
+        add_urls_to_vec(urls, &link, &mut self.links, &self.fetched_cache);
     }
 
-    for token in result {
-        trace!("token {:?}", token);
+    fn process_tag(&mut self, tag: Tag) {
+        self.document.process_tag(&tag, &self.url);
 
-        match token {
-            TagToken(tag) => {
-                if &tag.name == "meta" && (tag.kind == StartTag || tag.self_closing) {
-                    let mut ok = false;
-
-                    let mut found_meta = ("".to_string(), "".to_string());
-                    for attribute in tag.attrs.clone() {
-                        // TODO Use match {}
-                        if &attribute.name.local == "name" {
-                            found_meta.0 = (&attribute.value).to_string();
-                        } else if &attribute.name.local == "content" {
-                            found_meta.1 = (&attribute.value).to_string();
-                        }
-
-                        if &attribute.name.local == "name"
-                            && (attribute.value == Tendril::from_slice("robots")
-                                || attribute.value == Tendril::from_slice("twentiethbot"))
-                        {
-                            ok = true;
-                        }
-                    }
+        if &tag.name == "style" {
+            if tag.kind == StartTag && !tag.self_closing {
+                self.style = Some(String::new());
+            } else if let Some(css) = self.style.take() {
+                self.add_css_links(&css, "style", None);
+            }
+        }
+        if &tag.name == "script" {
+            if tag.kind == StartTag && !tag.self_closing {
+                // only inline scripts, as others aren't fetched to be read
+                if !tag.attrs.iter().any(|x| &x.name.local == "src") {
+                    self.script = Some(String::new());
+                }
+            } else if let Some(script) = self.script.take() {
+                if let Some(found) = script_redirect(&script) {
+                    self.add_redirect(&found, "script", "location", 0);
+                }
+            }
+        }
+        if tag.kind == StartTag {
+            for attribute in &tag.attrs {
+                if &attribute.name.local == "style" {
+                    self.add_css_links(&attribute.value, &tag.name, Some("style"));
+                }
+            }
+        }
 
-                    meta.push(found_meta);
+        // <a>s can't be nested, so a new one closes the last
+        if &tag.name == "a" {
+            close_anchor(&mut self.anchor, &mut self.links);
+        } else if &tag.name == "img" && tag.kind == StartTag && self.anchor.is_some() {
+            for attribute in &tag.attrs {
+                if &attribute.name.local == "alt" {
+                    let image_alt = &mut self.anchor.as_mut().unwrap().image_alt;
+                    image_alt.push(' ');
+                    image_alt.push_str(&attribute.value);
+                }
+            }
+        }
 
-                    if !ok {
-                        continue;
-                    }
+        if &tag.name == "meta" && (tag.kind == StartTag || tag.self_closing) {
+            let mut ok = false;
+            let mut refresh = false;
 
-                    for attribute in tag.attrs {
-                        if &attribute.name.local != "content" {
-                            continue;
-                        }
-
-                        for robots_command in attribute.value.split(",").map(|x| x.to_lowercase()) {
-                            debug!("found robot-command {}", robots_command);
-
-                            match robots_command.as_str() {
-                                "nofollow" => {
-                                    return None;
-                                }
-                                "noindex" => {
-                                    index_url = true;
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                } else if tag.kind == StartTag && tag.attrs.len() != 0 {
-                    let attribute_name = get_attribute_for_elem(&tag.name);
+            let mut found_meta = ("".to_string(), "".to_string());
+            for attribute in tag.attrs.clone() {
+                // TODO Use match {}
+                if &attribute.name.local == "name" {
+                    found_meta.0 = (&attribute.value).to_string();
+                } else if &attribute.name.local == "content" {
+                    found_meta.1 = (&attribute.value).to_string();
+                } else if &attribute.name.local == "http-equiv" {
+                    refresh = attribute.value.trim().eq_ignore_ascii_case("refresh");
+                }
 
-                    if attribute_name == None {
-                        continue;
-                    }
+                if &attribute.name.local == "name"
+                    && (attribute.value == Tendril::from_slice("robots")
+                        || attribute.value == Tendril::from_slice("twentiethbot"))
+                {
+                    ok = true;
+                }
+            }
 
-                    for attribute in &tag.attrs {
-                        if &attribute.name.local != attribute_name.unwrap() {
-                            continue;
-                        }
-
-                        trace!("element {:?} found", tag);
-                        add_urls_to_vec(
-                            repair_suggested_url(
-                                &original_url,
-                                (&attribute.name.local, &attribute.value),
-                            ),
-                            &mut found_urls,
-                            &fetched_cache,
-                        );
+            if refresh {
+                match parse_refresh(&found_meta.1) {
+                    Some((delay, Some(found))) => {
+                        self.add_redirect(&found, "meta", "content", delay)
                     }
+                    Some((_, None)) => trace!("{} refreshes itself", self.url),
+                    None => debug!("bad refresh {:?} in {}", found_meta.1, self.url),
+                }
+            }
+
+            self.meta.push(found_meta);
+
+            if !ok {
+                return;
+            }
+
+            for attribute in tag.attrs {
+                if &attribute.name.local != "content" {
+                    continue;
+                }
+
+                self.directives.apply(&attribute.value);
+            }
+        } else if tag.kind == StartTag && tag.attrs.len() != 0 {
+            let attribute_names = get_attributes_for_elem(&tag.name);
+
+            if attribute_names.len() == 0 {
+                return;
+            }
+
+            let mut link = Link::new(String::new());
+            link.element = tag.name.to_string();
+            let mut method = "get".to_string();
+            for attribute in &tag.attrs {
+                if &attribute.name.local == "rel" {
+                    link.rel = parse_rel(&attribute.value);
+                } else if &attribute.name.local == "method" {
+                    method = attribute.value.trim().to_lowercase();
+                } else if &attribute.name.local == "title" {
+                    link.title = normalize_link_text(&attribute.value);
+                } else if &attribute.name.local == "type" {
+                    link.media_type = attribute.value.trim().to_lowercase();
+                }
+            }
+
+            // submitting a POST form could change something, so only GET ones are links
+            if &tag.name == "form" && method != "get" {
+                trace!("ignoring form with method {}", method);
+                return;
+            }
+
+            let links_before = self.links.len();
+            for attribute in &tag.attrs {
+                if !attribute_names.iter().any(|x| *x == &*attribute.name.local) {
+                    continue;
+                }
+
+                trace!("element {:?} found", tag);
+                link.attribute = attribute.name.local.to_string();
+
+                let values = if &attribute.name.local == "srcset" {
+                    parse_srcset(&attribute.value)
+                } else {
+                    vec![attribute.value.to_string()]
+                };
+
+                for value in values {
+                    add_urls_to_vec(
+                        repair_suggested_url(&self.url, (&attribute.name.local, &value)),
+                        &link,
+                        &mut self.links,
+                        &self.fetched_cache,
+                    );
+                }
+            }
+
+            // the text up to </a> belongs to the link (if it wasn't a duplicate)
+            if &tag.name == "a" && self.links.len() > links_before {
+                self.anchor = Some(OpenAnchor {
+                    index: links_before,
+                    text: String::new(),
+                    image_alt: String::new(),
+                });
+            }
+        }
+    }
+
+    fn finish(mut self) -> CrawlResult {
+        close_anchor(&mut self.anchor, &mut self.links);
+        if let Some(css) = self.style.take() {
+            self.add_css_links(&css, "style", None);
+        }
+
+        if !self.directives.follow {
+            debug!("not following links on {} (nofollow)", self.url);
+            self.links.clear();
+        }
+
+        return CrawlResult {
+            index: self.directives.index,
+            links: self.links,
+            kind: "html".to_string(),
+            meta: self.meta,
+            document: Some(self.document.finish()),
+            entries: Vec::new(),
+            redirect: self.redirect,
+        };
+    }
+}
+
+impl TokenSink for HtmlTokenSink {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        trace!("token {:?}", token);
+
+        // without a tree builder, the tokenizer needs to be told which elements hold raw text
+        let result = match token {
+            TagToken(ref tag) if tag.kind == StartTag && !tag.self_closing => match &*tag.name {
+                "script" => TokenSinkResult::RawData(RawKind::ScriptData),
+                "style" | "xmp" | "iframe" | "noembed" | "noframes" => {
+                    TokenSinkResult::RawData(RawKind::Rawtext)
+                }
+                "title" | "textarea" => TokenSinkResult::RawData(RawKind::Rcdata),
+                "plaintext" => TokenSinkResult::Plaintext,
+                _ => TokenSinkResult::Continue,
+            },
+            _ => TokenSinkResult::Continue,
+        };
+
+        match token {
+            TagToken(tag) => self.process_tag(tag),
+            CharacterTokens(text) => {
+                self.document.process_text(&text);
+
+                if let Some(ref mut anchor) = self.anchor {
+                    push_capped(&mut anchor.text, &text, MAX_ANCHOR_TEXT_BYTES);
+                }
+                if let Some(ref mut style) = self.style {
+                    push_capped(style, &text, MAX_STYLE_BYTES);
+                }
+                if self
+                    .script
+                    .as_ref()
+                    .map_or(false, |x| x.len() + text.len() > MAX_SCRIPT_BYTES)
+                {
+                    self.script = None;
+                } else if let Some(ref mut script) = self.script {
+                    script.push_str(&text);
                 }
             }
             ParseError(error) => {
-                debug!("error parsing html for {}: {:?}", original_url, error);
+                debug!("error parsing html for {}: {:?}", self.url, error);
             }
             _ => {}
         }
+
+        return result;
+    }
+}
+
+/// Parses a page as it's fed in, a chunk at a time (say, as it comes off the
+/// network), so only what's been extracted so far is kept rather than the whole
+/// page and all of its tokens.
+pub struct HtmlParser {
+    tokenizer: Tokenizer<HtmlTokenSink>,
+    queue: BufferQueue,
+    decoder: Decoder,
+}
+
+impl HtmlParser {
+    pub fn new(url: Url, fetched_cache: Vec<String>, encoding: &'static Encoding) -> HtmlParser {
+        HtmlParser {
+            tokenizer: Tokenizer::new(
+                HtmlTokenSink::new(url, fetched_cache),
+                TokenizerOpts::default(),
+            ),
+            queue: BufferQueue::new(),
+            decoder: encoding.new_decoder_with_bom_removal(),
+        }
+    }
+
+    /// Feeds in some already-decoded text.
+    pub fn feed(&mut self, text: &str) {
+        if text.len() == 0 {
+            return;
+        }
+
+        self.queue.push_back(StrTendril::from_slice(text));
+        let _feed = self.tokenizer.feed(&mut self.queue);
+    }
+
+    // decodes bytes in the page's encoding, keeping partial characters for next time
+    fn decode(&mut self, bytes: &[u8], last: bool) -> String {
+        let length = self
+            .decoder
+            .max_utf8_buffer_length(bytes.len())
+            .expect("chunk too big to decode");
+        let mut text = String::with_capacity(length);
+        let _decoded = self.decoder.decode_to_string(bytes, &mut text, last);
+        return text;
     }
 
-    return Some((index_url, found_urls, "html".to_string(), meta));
+    /// Feeds in the next part of the page's body.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        let text = self.decode(bytes, false);
+        self.feed(&text);
+    }
+
+    pub fn finish(mut self) -> CrawlResult {
+        let text = self.decode(&[], true);
+        self.feed(&text);
+        self.tokenizer.end();
+
+        return self.tokenizer.sink.finish();
+    }
+}
+
+// how much of a body is handed to the parser at once
+static PARSE_CHUNK_SIZE: usize = 8192;
+
+// reads a page as it downloads, holding back its start until there's enough to
+// tell its encoding from
+struct HtmlBodyParser {
+    url: Url,
+    content_type: String,
+    start: Vec<u8>,
+    parser: Option<HtmlParser>,
+}
+
+impl HtmlBodyParser {
+    fn new(url: &Url, headers: &Headers) -> HtmlBodyParser {
+        HtmlBodyParser {
+            url: url.clone(),
+            content_type: content_type(headers),
+            start: Vec::new(),
+            parser: None,
+        }
+    }
+
+    fn begin(&mut self) {
+        if self.parser.is_some() {
+            return;
+        }
+
+        let (encoding, _) = charset::sniff(&self.start, &self.content_type);
+        let mut parser = HtmlParser::new(self.url.clone(), Vec::new(), encoding);
+        for chunk in self.start.chunks(PARSE_CHUNK_SIZE) {
+            parser.feed_bytes(chunk);
+        }
+        self.start = Vec::new();
+        self.parser = Some(parser);
+    }
+}
+
+impl BodyParser for HtmlBodyParser {
+    fn feed(&mut self, chunk: &[u8]) {
+        match self.parser {
+            Some(ref mut parser) => parser.feed_bytes(chunk),
+            None => {
+                self.start.extend_from_slice(chunk);
+                // past this, more of the body can't change what encoding it's in
+                if self.start.len() > charset::DETECT_BYTES {
+                    self.begin();
+                }
+            }
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> CrawlResult {
+        self.begin();
+        return self.parser.take().unwrap().finish();
+    }
+}
+
+/// Handles HTML and XHTML pages.
+pub struct HtmlHandler;
+
+impl ContentHandler for HtmlHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "text/html" || mime == "application/xhtml+xml"
+    }
+
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult {
+        let mut parser = Box::new(HtmlBodyParser::new(url, headers));
+        for chunk in body.chunks(PARSE_CHUNK_SIZE) {
+            parser.feed(chunk);
+        }
+        return parser.finish();
+    }
+
+    fn start(&self, url: &Url, headers: &Headers) -> Option<Box<dyn BodyParser>> {
+        Some(Box::new(HtmlBodyParser::new(url, headers)))
+    }
+}
+
+#[cfg(test)]
+pub fn find_urls_in_html(
+    original_url: Url,
+    raw_html: String,
+    fetched_cache: Vec<String>,
+) -> Option<CrawlResult> {
+    let mut parser = HtmlParser::new(original_url, fetched_cache, ::encoding_rs::UTF_8);
+    parser.feed(&raw_html);
+    return Some(parser.finish());
 }
 
 #[cfg(test)]
 mod tests {
     use html::*;
+    use document::Heading;
+    use encoding_rs::SHIFT_JIS;
 
     #[test]
-    fn _get_attribute_for_elem() {
-        assert_eq!(get_attribute_for_elem("a"), Some("href"));
-        assert_eq!(get_attribute_for_elem("script"), Some("src"));
-        assert_eq!(get_attribute_for_elem("link"), Some("href"));
-        assert_eq!(get_attribute_for_elem("img"), Some("src"));
-        assert_eq!(get_attribute_for_elem("iframe"), Some("src"));
-        assert_eq!(get_attribute_for_elem("amp-img"), Some("src"));
-        assert_eq!(get_attribute_for_elem("amp-anim"), Some("src"));
-        assert_eq!(get_attribute_for_elem("amp-video"), Some("src"));
-        assert_eq!(get_attribute_for_elem("amp-audio"), Some("src"));
-        assert_eq!(get_attribute_for_elem("amp-iframe"), Some("src"));
-        assert_eq!(get_attribute_for_elem("p"), None);
+    fn _get_attributes_for_elem() {
+        assert_eq!(get_attributes_for_elem("a"), &["href"]);
+        assert_eq!(get_attributes_for_elem("area"), &["href"]);
+        assert_eq!(get_attributes_for_elem("script"), &["src"]);
+        assert_eq!(get_attributes_for_elem("link"), &["href"]);
+        assert_eq!(get_attributes_for_elem("img"), &["src", "srcset"]);
+        assert_eq!(get_attributes_for_elem("iframe"), &["src"]);
+        assert_eq!(get_attributes_for_elem("source"), &["src", "srcset"]);
+        assert_eq!(get_attributes_for_elem("embed"), &["src"]);
+        assert_eq!(get_attributes_for_elem("object"), &["data"]);
+        assert_eq!(get_attributes_for_elem("video"), &["src", "poster"]);
+        assert_eq!(get_attributes_for_elem("track"), &["src"]);
+        assert_eq!(get_attributes_for_elem("form"), &["action"]);
+        assert_eq!(get_attributes_for_elem("blockquote"), &["cite"]);
+        assert_eq!(get_attributes_for_elem("amp-img"), &["src", "srcset"]);
+        assert_eq!(get_attributes_for_elem("amp-anim"), &["src", "srcset"]);
+        assert_eq!(get_attributes_for_elem("amp-video"), &["src", "poster"]);
+        assert_eq!(get_attributes_for_elem("amp-audio"), &["src"]);
+        assert_eq!(get_attributes_for_elem("amp-iframe"), &["src"]);
+        assert_eq!(get_attributes_for_elem("p").len(), 0);
+    }
+
+    #[test]
+    fn _normalize_link_text() {
+        assert_eq!(normalize_link_text("  a\n\tb  c "), "a b c");
+        assert_eq!(
+            normalize_link_text(&"ü".repeat(1000)).chars().count(),
+            MAX_LINK_TEXT_LENGTH
+        );
+    }
+
+    #[test]
+    fn _push_capped() {
+        let mut buffer = "ab".to_string();
+        push_capped(&mut buffer, "cdé", 4);
+        assert_eq!(buffer, "abcd");
+        push_capped(&mut buffer, "ü", 5);
+        assert_eq!(buffer, "abcd");
+        push_capped(&mut buffer, "ü", 6);
+        assert_eq!(buffer, "abcdü");
     }
 
     #[test]
     fn _html_token_sink() {
-        let mut result = Vec::new();
+        let mut sink = HtmlTokenSink::new(Url::parse("https://google.com/").unwrap(), Vec::new());
+        let tag = |name: &str| {
+            TagToken(Tag {
+                kind: StartTag,
+                name: name.into(),
+                self_closing: false,
+                attrs: Vec::new(),
+            })
+        };
+
+        assert_eq!(sink.process_token(EOFToken, 0), TokenSinkResult::Continue);
+        assert_eq!(
+            sink.process_token(tag("script"), 0),
+            TokenSinkResult::RawData(RawKind::ScriptData)
+        );
+        assert_eq!(
+            sink.process_token(tag("title"), 0),
+            TokenSinkResult::RawData(RawKind::Rcdata)
+        );
+        assert_eq!(sink.process_token(tag("p"), 0), TokenSinkResult::Continue);
+
+        // an anchor's text stops being kept once it's far longer than it can be
+        let mut sink = HtmlTokenSink::new(Url::parse("https://google.com/").unwrap(), Vec::new());
+        sink.anchor = Some(OpenAnchor {
+            index: 0,
+            text: String::new(),
+            image_alt: String::new(),
+        });
+        for _ in 0..1000 {
+            assert_eq!(
+                sink.process_token(CharacterTokens("天気 ".into()), 0),
+                TokenSinkResult::Continue
+            );
+        }
+        assert!(sink.anchor.unwrap().text.len() <= MAX_ANCHOR_TEXT_BYTES);
+    }
 
-        {
-            let mut sink = HtmlTokenSink(&mut result);
+    #[test]
+    fn _html_parser() {
+        let url = Url::parse("https://google.com/").unwrap();
+        let page = "<title>ニュース</title><a href='news/天気'>天気 &amp; ニュース</a>";
+        let (body, _, _) = SHIFT_JIS.encode(page);
 
-            assert_eq!(sink.0.len(), 0);
-            assert_eq!(sink.process_token(EOFToken, 0), TokenSinkResult::Continue);
-            assert_eq!(sink.0.len(), 1);
+        // one byte at a time splits up characters, entities and tags
+        let mut parser = HtmlParser::new(url.clone(), Vec::new(), SHIFT_JIS);
+        for byte in body.iter() {
+            parser.feed_bytes(&[*byte]);
         }
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0], EOFToken);
+        let result = parser.finish();
+
+        assert_eq!(
+            Some(result.clone()),
+            find_urls_in_html(url, page.to_string(), Vec::new())
+        );
+        assert_eq!(result.document.unwrap().title, "ニュース");
+        assert_eq!(result.links[0].anchor_text, "天気 & ニュース");
+        assert_eq!(
+            result.links[0].url,
+            "https://google.com/news/%E5%A4%A9%E6%B0%97"
+        );
     }
 
     #[test]
@@ -188,26 +663,46 @@ mod tests {
             inp.to_string()
         }
 
+        #[allow(non_snake_case)]
+        fn L(inp: &str, element: &str, attribute: &str) -> Link {
+            Link {
+                element: element.to_string(),
+                attribute: attribute.to_string(),
+                ..Link::new(inp.to_string())
+            }
+        }
+
+        // the parts of a CrawlResult that come from links and <meta>s
+        #[allow(non_snake_case)]
+        fn T(
+            result: Option<CrawlResult>,
+        ) -> Option<(bool, Vec<Link>, String, Vec<(String, String)>)> {
+            result.map(|x| (x.index, x.links, x.kind, x.meta))
+        }
+
         let orig = Url::parse("https://google.com/").unwrap();
         assert_eq!(
-            find_urls_in_html(
+            T(find_urls_in_html(
                 orig.clone(),
                 S("<a href='news'></a><a href='gmail'></a>"),
                 Vec::new()
-            ),
+            )),
             Some((
                 true,
-                vec![S("https://google.com/news"), S("https://google.com/gmail")],
+                vec![
+                    L("https://google.com/news", "a", "href"),
+                    L("https://google.com/gmail", "a", "href"),
+                ],
                 S("html"),
                 Vec::new()
             ))
         );
         assert_eq!(
-            find_urls_in_html(
+            T(find_urls_in_html(
                 orig.clone(),
                 S("<meta name='terminator' content='destroy' />"),
                 Vec::new()
-            ),
+            )),
             Some((
                 true,
                 Vec::new(),
@@ -216,17 +711,266 @@ mod tests {
             ))
         );
         assert_eq!(
-            find_urls_in_html(
+            T(find_urls_in_html(
                 orig.clone(),
                 S("<a href='news'></a><a href='gmail'></a>"),
                 vec![S("https://google.com/news")]
-            ),
+            )),
             Some((
                 true,
-                vec![S("https://google.com/gmail")],
+                vec![L("https://google.com/gmail", "a", "href")],
                 S("html"),
                 Vec::new()
             ))
         );
+        assert_eq!(
+            T(find_urls_in_html(
+                orig.clone(),
+                S("<meta name='robots' content='noindex, nofollow' /><a href='news'></a>"),
+                Vec::new()
+            )),
+            Some((
+                false,
+                Vec::new(),
+                S("html"),
+                vec![(S("robots"), S("noindex, nofollow"))]
+            ))
+        );
+        assert_eq!(
+            T(find_urls_in_html(
+                orig.clone(),
+                S("<a href='news' rel='Nofollow noopener'></a>"),
+                Vec::new()
+            )),
+            Some((
+                true,
+                vec![Link {
+                    rel: vec![S("nofollow"), S("noopener")],
+                    ..L("https://google.com/news", "a", "href")
+                }],
+                S("html"),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            T(find_urls_in_html(
+                orig.clone(),
+                S("<img src='a.png' srcset='a-2x.png 2x, /b,c.png 3x'>\
+                   <video poster='poster.jpg'><source src='v.webm'><track src='v.vtt'></video>\
+                   <map><area href='news'></map><object data='o.swf'></object>\
+                   <form action='search'></form><form method='POST' action='login'></form>\
+                   <blockquote cite='gmail'></blockquote>"),
+                Vec::new()
+            )),
+            Some((
+                true,
+                vec![
+                    L("https://google.com/a.png", "img", "src"),
+                    L("https://google.com/a-2x.png", "img", "srcset"),
+                    L("https://google.com/b,c.png", "img", "srcset"),
+                    L("https://google.com/poster.jpg", "video", "poster"),
+                    L("https://google.com/v.webm", "source", "src"),
+                    L("https://google.com/v.vtt", "track", "src"),
+                    L("https://google.com/news", "area", "href"),
+                    L("https://google.com/o.swf", "object", "data"),
+                    L("https://google.com/search", "form", "action"),
+                    L("https://google.com/gmail", "blockquote", "cite"),
+                ],
+                S("html"),
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            T(find_urls_in_html(
+                orig.clone(),
+                S(
+                    "<style>@import 'print.css'; body { background: url(bg.png) }</style>\
+                   <div style='background: url(\"/img/a.png\")'><a href='news'></a></div>\
+                   <p style='background: url(//bad host/a.png), url(//maps.google.com/b.png)'>"
+                ),
+                Vec::new()
+            ))
+            .unwrap()
+            .1,
+            vec![
+                L("https://google.com/print.css", "style", "import"),
+                L("https://google.com/bg.png", "style", "url"),
+                L("https://google.com/img/a.png", "div", "style"),
+                L("https://google.com/news", "a", "href"),
+                L("https://maps.google.com/b.png", "p", "style"),
+            ]
+        );
+        assert_eq!(
+            T(find_urls_in_html(
+                orig.clone(),
+                S(
+                    "<a href='news' title=' Google\nNews '>Top\n   <b>stories</b> </a>\
+                   <a href='gmail'><img src='mail.png' alt='Mail'>Gmail<a href='maps'>Maps"
+                ),
+                Vec::new()
+            )),
+            Some((
+                true,
+                vec![
+                    Link {
+                        anchor_text: S("Top stories"),
+                        title: S("Google News"),
+                        ..L("https://google.com/news", "a", "href")
+                    },
+                    Link {
+                        anchor_text: S("Gmail"),
+                        image_alt: S("Mail"),
+                        ..L("https://google.com/gmail", "a", "href")
+                    },
+                    L("https://google.com/mail.png", "img", "src"),
+                    Link {
+                        anchor_text: S("Maps"),
+                        ..L("https://google.com/maps", "a", "href")
+                    },
+                ],
+                S("html"),
+                Vec::new()
+            ))
+        );
+    }
+
+    #[test]
+    fn _find_redirects_in_html() {
+        let url = Url::parse("https://google.com/old/").unwrap();
+        let result = find_urls_in_html(
+            url.clone(),
+            "<meta http-equiv='Refresh' content='5; URL=../news'>\
+             <script>window.location = '/maps';</script>"
+                .to_string(),
+            Vec::new(),
+        )
+        .unwrap();
+
+        // the first redirect is the page's, but both are followed
+        assert_eq!(
+            result.redirect,
+            Some(PageRedirect {
+                url: "https://google.com/news".to_string(),
+                delay: 5,
+            })
+        );
+        assert_eq!(
+            result
+                .links
+                .iter()
+                .map(|x| (x.url.as_str(), x.element.as_str(), x.attribute.as_str()))
+                .collect::<Vec<(&str, &str, &str)>>(),
+            vec![
+                ("https://google.com/news", "meta", "content"),
+                ("https://google.com/maps", "script", "location"),
+            ]
+        );
+        assert_eq!(
+            result.meta,
+            vec![(String::new(), "5; URL=../news".to_string())]
+        );
+
+        let result = find_urls_in_html(
+            url.clone(),
+            "<script>\n  location.replace(\"https://google.com/mail\");\n</script>".to_string(),
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.redirect,
+            Some(PageRedirect {
+                url: "https://google.com/mail".to_string(),
+                delay: 0,
+            })
+        );
+
+        // reloading itself, scripts that do more, and scripts that aren't inline
+        for html in &[
+            "<meta http-equiv='refresh' content='30'>",
+            "<script>if (old) { window.location = '/maps'; }</script>",
+            "<script src='location.js'>window.location = '/maps';</script>",
+        ] {
+            let result = find_urls_in_html(url.clone(), html.to_string(), Vec::new()).unwrap();
+            assert_eq!(result.redirect, None, "{}", html);
+        }
+
+        // a redirect at the start of a long script isn't all it does
+        let html = format!(
+            "<script>window.location = '/maps';{}</script>",
+            " ".repeat(MAX_SCRIPT_BYTES)
+        );
+        let result = find_urls_in_html(url.clone(), html, Vec::new()).unwrap();
+        assert_eq!(result.redirect, None);
+    }
+
+    #[test]
+    fn _find_urls_in_html_long_style() {
+        let url = Url::parse("https://google.com/").unwrap();
+        let html = format!(
+            "<style>a {{ background: url(a.png) }}{}b {{ background: url(b.png) }}",
+            " ".repeat(MAX_STYLE_BYTES)
+        );
+
+        let mut parser = HtmlParser::new(url, Vec::new(), ::encoding_rs::UTF_8);
+        parser.feed(&html);
+        assert!(parser.tokenizer.sink.style.as_ref().unwrap().len() <= MAX_STYLE_BYTES);
+
+        // only what was kept is read
+        let urls: Vec<String> = parser.finish().links.into_iter().map(|x| x.url).collect();
+        assert_eq!(urls, vec!["https://google.com/a.png".to_string()]);
+    }
+
+    #[test]
+    fn _find_urls_in_html_document() {
+        let html = "<!doctype html><html lang=' en-GB '><head>\
+                    <title>Google\n Search</title><title>Other</title>\
+                    <meta name='Description' content=' Search the\n web '>\
+                    <link rel='canonical' href='/search'>\
+                    <style>p { color: red }</style><script>var a = '<h1>';</script>\
+                    </head><body><nav>Menu</nav>\
+                    <main><h1>Results <b>for</b> cats</h1><p>First<br>result</p>\
+                    <h2></h2><h3>More</h3></main>\
+                    <footer>Copyright</footer></body></html>";
+        let document = find_urls_in_html(
+            Url::parse("https://google.com/?q=cats").unwrap(),
+            html.to_string(),
+            Vec::new(),
+        )
+        .unwrap()
+        .document
+        .unwrap();
+
+        assert_eq!(document.title, "Google Search");
+        assert_eq!(document.description, "Search the web");
+        assert_eq!(document.canonical, "https://google.com/search");
+        assert_eq!(document.lang, "en-GB");
+        assert_eq!(
+            document.headings,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Results for cats".to_string(),
+                },
+                Heading {
+                    level: 3,
+                    text: "More".to_string(),
+                },
+            ]
+        );
+        assert_eq!(document.text, "Results for cats First result More");
+        assert_eq!(document.word_count, 6);
+
+        // without a <main>, all of the body counts
+        let document = find_urls_in_html(
+            Url::parse("https://google.com/").unwrap(),
+            "<p>Hello <i>world</i></p><noscript>Enable JavaScript</noscript><p>Bye".to_string(),
+            Vec::new(),
+        )
+        .unwrap()
+        .document
+        .unwrap();
+        assert_eq!(document.text, "Hello world Bye");
+        assert_eq!(document.word_count, 3);
+        assert_eq!(document.title, "");
     }
 }
@@ -0,0 +1,1133 @@
+extern crate base32;
+extern crate brotli_decompressor;
+extern crate chardetng;
+extern crate chrono;
+extern crate encoding_rs;
+extern crate flate2;
+extern crate html5ever;
+#[macro_use]
+extern crate log;
+#[cfg_attr(test, macro_use)]
+extern crate lopdf;
+extern crate reqwest;
+#[macro_use]
+extern crate rusqlite;
+extern crate robotparser;
+extern crate serde_json;
+extern crate sha1;
+#[macro_use]
+extern crate serde_derive;
+extern crate url;
+extern crate uuid;
+extern crate xml;
+extern crate zstd;
+
+// see issue #7
+//#[cfg(test)]
+//extern crate iron;
+
+use chrono::{DateTime, Utc};
+use document::{CrawlResult, PageDocument};
+use feed::EntryWriter;
+use frontier::Frontier;
+use handler::Handlers;
+use link::{Link, LinkKind};
+use link_graph::LinkGraph;
+use options::{Compression, LinkPolicy, NofollowPolicy, Options, SinkKind};
+use output::{PageRecord, PageWriter};
+use rotate::RotatingFile;
+use sink::{Fetch, Finish, OutputSink, Sinks};
+use reqwest::Client;
+use robotparser::RobotFileParser;
+use sitemap::{Sitemap, SitemapEntry};
+use sqlite::SqliteSink;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::time::{Duration, Instant};
+use url::{Position, Url};
+use warc::{WarcReader, WarcRecord, WarcWriter};
+
+mod charset;
+mod css;
+pub mod document;
+mod download;
+pub mod feed;
+mod frontier;
+pub mod handler;
+mod html;
+pub mod link;
+mod link_graph;
+mod mime_sniff;
+pub mod options;
+mod output;
+pub mod pagerank;
+mod pdf;
+mod redirect;
+mod robots_tag;
+mod rotate;
+mod sink;
+mod sitemap;
+mod sqlite;
+mod text;
+mod url_utils;
+mod warc;
+
+// `headers` should have the Content-Type the body was sniffed as, which may
+// not be what the server sent. `parsed` is what a handler already made of the
+// body while it downloaded, if one did.
+fn crawl_page(
+    handlers: &Handlers,
+    url: &str,
+    headers: &reqwest::header::Headers,
+    body: &[u8],
+    parsed: Option<CrawlResult>,
+    cache: Vec<String>,
+) -> Option<CrawlResult> {
+    let directives = robots_tag::find_in_headers(headers, "twentiethbot");
+
+    let parsed = parsed.or_else(|| handlers.process(&Url::parse(url).unwrap(), headers, body));
+    let mut result = match parsed {
+        Some(result) => result,
+        None => return noindex_result(directives),
+    };
+    result
+        .links
+        .retain(|x| url_utils::check_if_is_in_url_list(&x.url, &cache));
+
+    // X-Robots-Tag and <meta name="robots"> make up the same policy
+    result.index = result.index && directives.index;
+    if !directives.follow {
+        debug!("not following links on {} (X-Robots-Tag)", url);
+        result.links.clear();
+    }
+
+    return Some(result);
+}
+
+fn noindex_result(directives: robots_tag::RobotsDirectives) -> Option<CrawlResult> {
+    if directives.index {
+        return None;
+    }
+
+    // X-Robots-Tag is the only way to mark non-HTML documents noindex
+    return Some(CrawlResult {
+        index: false,
+        ..CrawlResult::new("")
+    });
+}
+
+// response headers kept in page records
+static RECORDED_HEADERS: [&str; 7] = [
+    "content-length",
+    "content-language",
+    "last-modified",
+    "etag",
+    "cache-control",
+    "server",
+    "x-robots-tag",
+];
+
+fn recorded_headers(headers: &reqwest::header::Headers) -> BTreeMap<String, String> {
+    let mut recorded = BTreeMap::new();
+
+    for name in RECORDED_HEADERS.iter() {
+        if let Some(raw) = headers.get_raw(name) {
+            let values: Vec<String> = raw
+                .iter()
+                .map(|x| String::from_utf8_lossy(x).trim().to_string())
+                .collect();
+            recorded.insert(name.to_string(), values.join(", "));
+        }
+    }
+
+    return recorded;
+}
+
+fn find_in_robot_cache<'a>(
+    object: &str,
+    array: Vec<(String, RobotFileParser<'a>)>,
+) -> Option<(String, RobotFileParser<'a>)> {
+    trace!("finding {} in robot_cache", object);
+    for entry in array {
+        trace!("discovered {} in robot_cache", entry.0);
+        if String::from(object) == entry.0 {
+            debug!("found {} in robot cache!", object);
+            return Some(entry);
+        }
+    }
+
+    debug!("couldn't find {} in robot_cache :(", object);
+    return None;
+}
+
+// decodes a body to UTF-8, working out its encoding like a browser would
+fn decode_text(
+    headers: &reqwest::header::Headers,
+    body: &[u8],
+) -> (String, &'static encoding_rs::Encoding) {
+    return charset::decode(body, &handler::content_type(headers));
+}
+
+// reqwest doesn't hand over what it sent, so this is the gist of it
+fn request_head(method: &str, url: &Url) -> Vec<u8> {
+    let mut host = url.host_str().unwrap_or("").to_string();
+    if let Some(port) = url.port() {
+        host.push_str(&format!(":{}", port));
+    }
+
+    return format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nAccept: */*\r\n\r\n",
+        method,
+        &url[Position::BeforePath..Position::AfterQuery],
+        host
+    )
+    .into_bytes();
+}
+
+fn response_head(response: &reqwest::Response) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {}\r\n{}\r\n",
+        response.status(),
+        response.headers()
+    )
+    .into_bytes()
+}
+
+// writes a fetch to the WARC files, if there are any
+fn archive(
+    warc: &mut Option<WarcWriter>,
+    method: &str,
+    response: &reqwest::Response,
+    body: &[u8],
+    truncated: &str,
+    time: DateTime<Utc>,
+    links: &[Link],
+) {
+    let writer = match *warc {
+        Some(ref mut writer) => writer,
+        None => return,
+    };
+
+    let metadata: Vec<(String, String)> = links
+        .iter()
+        .filter(|x| x.element.len() != 0)
+        .map(|x| {
+            (
+                "outlink".to_string(),
+                format!("{} {}/@{}", x.url, x.element, x.attribute),
+            )
+        })
+        .collect();
+
+    let url = response.url();
+    let result = writer.write_exchange(&warc::Exchange {
+        url: url.as_str(),
+        time: time,
+        request: &request_head(method, url),
+        response_head: &response_head(response),
+        body: body,
+        truncated: truncated,
+        metadata: &metadata,
+        reconstructed: true,
+    });
+    if let Err(error) = result {
+        warn!("error writing WARC records for {}: {}", url, error);
+    }
+}
+
+// Google ignores anything after the first 500KiB of a robots.txt
+static MAX_ROBOTS_TXT_BYTES: u64 = 500 * 1024;
+
+fn read_robots_txt(
+    client: &Client,
+    warc: &mut Option<WarcWriter>,
+    robotstxt_path: &Url,
+    robotstxt: &RobotFileParser,
+    max_time: Duration,
+) -> String {
+    let response = client.get(robotstxt_path.as_str()).send();
+
+    if response.is_err() {
+        warn!("request to {} failed: {:?}", robotstxt_path, response);
+        return String::new();
+    }
+
+    let mut response = response.unwrap();
+    let (body, truncated) = download::read_body(&mut response, MAX_ROBOTS_TXT_BYTES, max_time)
+        .unwrap_or_else(|error| {
+            warn!("error reading {}: {}", robotstxt_path, error);
+            (Vec::new(), None)
+        });
+    let truncated = truncated.map_or("", |x| x.as_str());
+    archive(warc, "GET", &response, &body, truncated, Utc::now(), &[]);
+
+    match response.status() {
+        reqwest::StatusCode::Unauthorized | reqwest::StatusCode::Forbidden => {
+            debug!("{} is forbidden, so is everything else", robotstxt_path);
+            robotstxt.parse(&["User-agent: *", "Disallow: /"]);
+            return String::new();
+        }
+        status if status.is_client_error() => {
+            debug!("no robots.txt at {} ({})", robotstxt_path, status);
+            robotstxt.parse::<&str>(&[]);
+            return String::new();
+        }
+        status if !status.is_success() => {
+            // leaving it unparsed disallows everything, like RobotFileParser::read()
+            warn!("robots.txt at {} returned {}", robotstxt_path, status);
+            return String::new();
+        }
+        _ => {}
+    }
+
+    let text = decode_text(response.headers(), &body).0;
+    robotstxt.parse(&text.lines().collect::<Vec<&str>>());
+    return text;
+}
+
+// a sitemap index can point to up to 50000 more, don't follow all of them
+static MAX_SITEMAPS_PER_HOST: usize = 64;
+
+fn fetch_sitemaps(
+    client: &Client,
+    warc: &mut Option<WarcWriter>,
+    robotstxt: &RobotFileParser,
+    sitemaps: Vec<String>,
+    max_time: Duration,
+) -> Vec<SitemapEntry> {
+    let mut queue = sitemaps;
+    let mut fetched = 0;
+    let mut entries = Vec::new();
+
+    while let Some(sitemap_url) = queue.pop() {
+        if fetched >= MAX_SITEMAPS_PER_HOST {
+            warn!("not fetching {} (too many sitemaps)", sitemap_url);
+            break;
+        }
+
+        if !robotstxt.can_fetch("twentiethbot", &sitemap_url) {
+            warn!("ignoring sitemap {} (forbidden by robots.txt)", sitemap_url);
+            continue;
+        }
+
+        info!("fetching sitemap {}!", sitemap_url);
+        fetched += 1;
+        let response = client.get(&sitemap_url).send();
+
+        if response.is_err() {
+            warn!("request to {} failed: {:?}", sitemap_url, response);
+            continue;
+        }
+
+        let mut response = response.unwrap();
+        let read = download::read_body(&mut response, sitemap::MAX_SITEMAP_SIZE, max_time);
+        let (body, truncated) = match read {
+            Ok(body) => body,
+            Err(error) => {
+                warn!("error reading {}: {}", sitemap_url, error);
+                continue;
+            }
+        };
+        let truncated = truncated.map_or("", |x| x.as_str());
+        archive(warc, "GET", &response, &body, truncated, Utc::now(), &[]);
+
+        if !response.status().is_success() {
+            debug!("no sitemap at {} ({})", sitemap_url, response.status());
+            continue;
+        }
+
+        match sitemap::parse_sitemap(&body) {
+            Some(Sitemap::UrlSet(mut found_entries)) => {
+                debug!("{} urls in sitemap {}", found_entries.len(), sitemap_url);
+                entries.append(&mut found_entries);
+            }
+            Some(Sitemap::Index(mut found_sitemaps)) => {
+                debug!("{} sitemaps in {}", found_sitemaps.len(), sitemap_url);
+                queue.append(&mut found_sitemaps);
+            }
+            None => {
+                warn!("couldn't parse sitemap {}", sitemap_url);
+            }
+        }
+    }
+
+    return entries;
+}
+
+// a response, from the network or a WARC file
+struct FetchedPage {
+    url: String,
+    final_url: String,
+    status: u16,
+    headers: reqwest::header::Headers,
+    body: Vec<u8>,
+    /// Why the body was cut short (`length` or `time`), if it was.
+    truncated: String,
+    depth: u32,
+    time: DateTime<Utc>,
+}
+
+fn open_sinks(options: &Options) -> Sinks {
+    let mut sinks = Sinks::new();
+
+    for spec in options.sinks() {
+        let writer = || -> Box<dyn Finish> {
+            if spec.path == "-" {
+                Box::new(io::stdout())
+            } else if spec.rotation.is_enabled() || spec.compression != Compression::None {
+                Box::new(RotatingFile::new(
+                    spec.path.clone(),
+                    spec.rotation,
+                    spec.compression,
+                ))
+            } else {
+                let file = File::create(&spec.path)
+                    .unwrap_or_else(|error| panic!("couldn't create {}: {}", spec.path, error));
+                Box::new(BufWriter::new(file))
+            }
+        };
+
+        let sink: Box<dyn OutputSink> = match spec.kind {
+            SinkKind::Pages(format) => Box::new(PageWriter::new(writer(), format)),
+            SinkKind::LinkGraph(format) => Box::new(LinkGraph::new(writer(), format)),
+            SinkKind::FeedEntries(format) => Box::new(EntryWriter::new(writer(), format)),
+            SinkKind::Sqlite => Box::new(
+                SqliteSink::open(&spec.path, sqlite::DEFAULT_BATCH_SIZE)
+                    .unwrap_or_else(|error| panic!("couldn't open {}: {}", spec.path, error)),
+            ),
+        };
+        sinks.add(spec.path.clone(), sink, spec.filter.clone());
+    }
+
+    debug!("writing to {} outputs", sinks.len());
+    return sinks;
+}
+
+// tells the sinks about a request
+fn log_fetch(
+    sinks: &mut Sinks,
+    url: &str,
+    method: &str,
+    status: Option<u16>,
+    error: Option<String>,
+    started: Instant,
+) {
+    let fetch = Fetch {
+        url: url,
+        method: method,
+        attempt: 1,
+        status: status,
+        error: error,
+        duration: started.elapsed(),
+        time: Utc::now(),
+    };
+
+    if fetch.error.is_some() {
+        sinks.on_error(&fetch);
+    } else {
+        sinks.on_fetch(&fetch);
+    }
+}
+
+// whether the response has X-Content-Type-Options: nosniff
+fn no_sniff(headers: &reqwest::header::Headers) -> bool {
+    headers
+        .get_raw("X-Content-Type-Options")
+        .and_then(|x| x.one())
+        .map_or(false, |x| x.eq_ignore_ascii_case(b"nosniff"))
+}
+
+// runs a page through crawl_page and writes out what was found
+fn process_page(
+    page: &FetchedPage,
+    parsed: Option<CrawlResult>,
+    handlers: &Handlers,
+    sinks: &mut Sinks,
+    cache: Vec<String>,
+) -> CrawlResult {
+    let declared_type = handler::content_type(&page.headers);
+    let sniffed_type = mime_sniff::sniff(&declared_type, &page.body, no_sniff(&page.headers));
+    if sniffed_type != declared_type {
+        debug!("{} is {}, not {:?}", page.url, sniffed_type, declared_type);
+    }
+
+    let mut headers = page.headers.clone();
+    headers.set_raw("Content-Type", sniffed_type.clone());
+
+    let (encoding, _) = charset::sniff(&page.body, &sniffed_type);
+    let found_urls = crawl_page(handlers, &page.url, &headers, &page.body, parsed, cache)
+        .unwrap_or(CrawlResult::new(""));
+
+    for link in &found_urls.links {
+        sinks.on_link(&page.url, link, page.time);
+    }
+    for entry in &found_urls.entries {
+        sinks.on_entry(entry);
+    }
+
+    if found_urls.index {
+        let document = found_urls.document.clone().unwrap_or_else(|| {
+            let text = charset::decode_with(encoding, &page.body);
+            PageDocument {
+                word_count: text.split_whitespace().count(),
+                text: text,
+                ..PageDocument::default()
+            }
+        });
+        let record = PageRecord {
+            url: page.url.clone(),
+            final_url: page.final_url.clone(),
+            redirect: found_urls.redirect.clone(),
+            status: page.status,
+            content_type: declared_type,
+            sniffed_type: sniffed_type,
+            encoding: encoding.name().to_string(),
+            truncated: page.truncated.clone(),
+            depth: page.depth,
+            fetch_time: page.time.to_rfc3339(),
+            headers: recorded_headers(&page.headers),
+            document: document,
+            meta: found_urls.meta.clone(),
+        };
+
+        sinks.on_page(&record, &page.body);
+    }
+
+    sinks.flush();
+    return found_urls;
+}
+
+// turns a WARC response record back into the page that was fetched
+fn replayed_page(record: &WarcRecord) -> Option<FetchedPage> {
+    let target = record
+        .header("WARC-Target-URI")?
+        .trim_matches(|c| c == '<' || c == '>');
+    // other crawlers' WARCs can have anything here
+    let url = match Url::parse(target) {
+        Ok(url) => url.to_string(),
+        Err(error) => {
+            warn!("bad WARC-Target-URI {:?}: {}", target, error);
+            return None;
+        }
+    };
+    let response = warc::parse_http_response(&record.block)?;
+
+    let mut headers = reqwest::header::Headers::new();
+    for (name, value) in response.headers {
+        // the body has already been decoded
+        if name.eq_ignore_ascii_case("Transfer-Encoding")
+            || name.eq_ignore_ascii_case("Content-Encoding")
+        {
+            continue;
+        }
+        headers.append_raw(name, value.into_bytes());
+    }
+
+    Some(FetchedPage {
+        final_url: url.clone(),
+        url: url,
+        status: response.status,
+        headers: headers,
+        body: response.body,
+        truncated: record.header("WARC-Truncated").unwrap_or("").to_string(),
+        depth: 0,
+        time: record
+            .header("WARC-Date")
+            .and_then(|x| x.parse::<DateTime<Utc>>().ok())
+            .unwrap_or_else(Utc::now),
+    })
+}
+
+/// Re-processes the responses in `options.replay`'s WARC files without
+/// touching the network.
+pub fn replay(options: &Options, handlers: &Handlers) {
+    let mut sinks = open_sinks(options);
+
+    for path in &options.replay {
+        info!("replaying {}!", path);
+        let reader = match WarcReader::open(path) {
+            Ok(reader) => reader,
+            Err(error) => {
+                error!("couldn't open {}: {}", path, error);
+                continue;
+            }
+        };
+
+        for record in reader {
+            let record = match record {
+                Ok(record) => record,
+                Err(error) => {
+                    warn!("error reading {}: {}", path, error);
+                    break;
+                }
+            };
+
+            if record.header("WARC-Type") != Some("response") {
+                continue;
+            }
+
+            match replayed_page(&record) {
+                Some(page) => {
+                    debug!("replaying {} from {}", page.url, path);
+                    process_page(&page, None, handlers, &mut sinks, Vec::new());
+                }
+                None => warn!(
+                    "couldn't read the response in {} ({:?})",
+                    path,
+                    record.header("WARC-Record-ID")
+                ),
+            }
+        }
+    }
+
+    sinks.close();
+}
+
+/// Crawls from `options.start_url` until there's nothing left, handing each
+/// body to whichever of `handlers` takes its type.
+pub fn crawl(options: &Options, handlers: &Handlers) {
+    _main_loop(options.start_url.clone(), options, handlers, true);
+}
+
+fn _main_loop(starton: String, options: &Options, handlers: &Handlers, panic: bool) -> Vec<String> {
+    let max_time = Duration::from_secs(options.max_download_seconds);
+    // see issue #7
+    let client = Client::builder()
+        .timeout(max_time)
+        .build()
+        .expect("couldn't create an HTTP client");
+    let mut frontier = Frontier::new();
+    let mut robots_cache: Vec<(String, RobotFileParser)> = Vec::new();
+    let mut fetched_cache: Vec<String> = Vec::new();
+    let mut sinks = open_sinks(options);
+    // link graphs and databases want links to pages we've already been to as well
+    let all_links = options.sinks().iter().any(|x| match x.kind {
+        SinkKind::Pages(_) | SinkKind::FeedEntries(_) => false,
+        SinkKind::LinkGraph(_) | SinkKind::Sqlite => true,
+    });
+    let mut warc = options
+        .warc
+        .as_ref()
+        .map(|prefix| WarcWriter::new(prefix.clone(), options.warc_max_size));
+
+    #[allow(unused_mut)]
+    let mut all_found_urls: Vec<String> = Vec::new();
+
+    frontier.push(starton, frontier::PRIORITY_NORMAL, 0);
+
+    loop {
+        let entry = match frontier.pop() {
+            Some(entry) => entry,
+            None => {
+                sinks.close();
+                if panic {
+                    panic!("no more urls???");
+                } else {
+                    return all_found_urls;
+                }
+            }
+        };
+        let url = entry.url;
+
+        debug!("url = {} ({} more in frontier)", url, frontier.len());
+        let parsed_url = Url::parse(&url).unwrap();
+        let mut hostname = String::from(parsed_url.host_str().unwrap()); // TODO Merge with previous line
+
+        // a HEAD check isn't a fetch, so the URL can still be crawled if it
+        // turns up as a page later
+        if !url_utils::check_if_is_in_url_list(&url, &fetched_cache) {
+            info!("[skipping {} (already fetched)]", url);
+            continue;
+        } else if !entry.head_only {
+            fetched_cache.push(url.clone());
+        }
+
+        let mut original_hostname = hostname.clone();
+
+        let mut _robotsok = find_in_robot_cache(&hostname, robots_cache.clone());
+        let mut robotsok: (String, RobotFileParser);
+
+        let mut robotstxt_path = parsed_url.clone();
+        robotstxt_path.set_path("/robots.txt");
+        robotstxt_path.set_query(None);
+
+        if _robotsok == None {
+            if robots_cache.len() > 512 {
+                debug!("clearing robots_cache");
+                robots_cache.clear();
+            }
+
+            debug!("fetching robots.txt, aka {}", robotstxt_path);
+            let robotstxt = RobotFileParser::new(&robotstxt_path);
+            let robotstxt_text =
+                read_robots_txt(&client, &mut warc, &robotstxt_path, &robotstxt, max_time);
+            robotsok = (String::from(original_hostname), robotstxt);
+            robots_cache.push(robotsok.clone());
+            debug!("finished, in cache");
+
+            if options.sitemaps {
+                let mut sitemaps = sitemap::find_sitemaps_in_robots(&robotstxt_text);
+                if sitemaps.len() == 0 {
+                    let mut sitemap_path = robotstxt_path.clone();
+                    sitemap_path.set_path("/sitemap.xml");
+                    sitemaps.push(sitemap_path.as_str().to_string());
+                }
+
+                let now = Utc::now();
+                for sitemap_entry in
+                    fetch_sitemaps(&client, &mut warc, &robotsok.1, sitemaps, max_time)
+                {
+                    let priority = sitemap_entry.frontier_priority(now);
+                    frontier.push(sitemap_entry.url, priority, entry.depth + 1);
+                }
+            }
+        } else {
+            robotsok = _robotsok.unwrap();
+        }
+
+        if !robotsok.1.can_fetch("twentiethbot", &url) {
+            warn!("ignoring {} (forbidden by robots.txt)", url);
+            continue;
+        }
+
+        if entry.head_only {
+            info!("checking {}!", url);
+            let started = Instant::now();
+            match client.head(&url).send() {
+                Ok(response) => {
+                    info!(
+                        "{} is {} ({:?})",
+                        url,
+                        response.status(),
+                        response.headers().get::<reqwest::header::ContentType>()
+                    );
+                    let status = Some(response.status().as_u16());
+                    log_fetch(&mut sinks, &url, "HEAD", status, None, started);
+                    archive(&mut warc, "HEAD", &response, &[], "", Utc::now(), &[]);
+                }
+                Err(error) => {
+                    warn!("request to {} failed: {:?}", url, error);
+                    let error = Some(error.to_string());
+                    log_fetch(&mut sinks, &url, "HEAD", None, error, started);
+                }
+            }
+            continue;
+        }
+
+        info!("fetching {}!", url);
+        let started = Instant::now();
+        let response = client.get(&url).send();
+
+        if let Err(ref error) = response {
+            warn!("request to {} failed: {:?}", url, error);
+            log_fetch(
+                &mut sinks,
+                &url,
+                "GET",
+                None,
+                Some(error.to_string()),
+                started,
+            );
+            continue;
+        }
+
+        let mut response = response.unwrap();
+        let status = Some(response.status().as_u16());
+        let declared_type = handler::content_type(response.headers());
+        let may_sniff = mime_sniff::may_sniff(&declared_type, no_sniff(response.headers()));
+        // a body whose type isn't known yet might turn out to be HTML, and a
+        // handler that needs more (or less) of a body than usual says so
+        let max_bytes = if may_sniff {
+            options.max_html_bytes
+        } else {
+            handlers.max_bytes(response.headers()).unwrap_or(
+                if mime_sniff::is_html(&declared_type) {
+                    options.max_html_bytes
+                } else {
+                    options.max_other_bytes
+                },
+            )
+        };
+        // a type sniffing can't change is known to be final, so a handler that
+        // can read it as it downloads gets to
+        let mut parser = if may_sniff {
+            None
+        } else {
+            handlers.start(&parsed_url, response.headers())
+        };
+        let read = download::read_body_with(&mut response, max_bytes, max_time, |chunk| {
+            if let Some(ref mut parser) = parser {
+                parser.feed(chunk);
+            }
+        });
+        let (body, truncated) = match read {
+            Ok(body) => body,
+            Err(error) => {
+                warn!("error reading {}: {}", url, error);
+                log_fetch(
+                    &mut sinks,
+                    &url,
+                    "GET",
+                    status,
+                    Some(error.to_string()),
+                    started,
+                );
+                continue;
+            }
+        };
+        if let Some(truncated) = truncated {
+            info!("cut {} short ({})", url, truncated.as_str());
+        }
+        log_fetch(&mut sinks, &url, "GET", status, None, started);
+
+        let page = FetchedPage {
+            url: url.clone(),
+            final_url: response.url().as_str().to_string(),
+            status: response.status().as_u16(),
+            headers: response.headers().clone(),
+            body: body,
+            truncated: truncated.map(|x| x.as_str()).unwrap_or("").to_string(),
+            depth: entry.depth,
+            time: Utc::now(),
+        };
+        let found_urls = process_page(
+            &page,
+            parser.map(|x| x.finish()),
+            handlers,
+            &mut sinks,
+            if all_links {
+                Vec::new()
+            } else {
+                fetched_cache.clone()
+            },
+        );
+        archive(
+            &mut warc,
+            "GET",
+            &response,
+            &page.body,
+            &page.truncated,
+            page.time,
+            &found_urls.links,
+        );
+
+        for link in &found_urls.links {
+            let policy = match link.kind() {
+                LinkKind::Navigation => options.navigation_links,
+                LinkKind::Resource => options.resource_links,
+                LinkKind::Frame => options.frame_links,
+            };
+
+            if policy == LinkPolicy::Record
+                || !(link.url.starts_with("http://") || link.url.starts_with("https://"))
+            {
+                trace!("not queueing {} ({:?})", link.url, link.kind());
+                continue;
+            }
+            // the sinks may have wanted every link, but the frontier doesn't
+            if all_links && !url_utils::check_if_is_in_url_list(&link.url, &fetched_cache) {
+                continue;
+            }
+
+            // a refresh or script redirect is followed like an HTTP one: soon, and
+            // without going any deeper
+            let redirect = found_urls
+                .redirect
+                .as_ref()
+                .map_or(false, |x| x.url == link.url);
+            let depth = if redirect {
+                entry.depth
+            } else {
+                entry.depth + 1
+            };
+
+            // feeds list what's new, so they and their items go first
+            let normal = if redirect || found_urls.kind == "feed" || link.is_feed() {
+                frontier::PRIORITY_HIGH
+            } else {
+                frontier::PRIORITY_NORMAL
+            };
+            let priority = if !link.is_nofollow() {
+                normal
+            } else {
+                match options.nofollow {
+                    NofollowPolicy::Follow => normal,
+                    NofollowPolicy::Deprioritize => frontier::PRIORITY_LOW,
+                    NofollowPolicy::Skip => {
+                        debug!("not following {} (rel={:?})", link.url, link.rel);
+                        continue;
+                    }
+                }
+            };
+
+            if policy == LinkPolicy::HeadOnly {
+                frontier.push_head_only(link.url.clone(), priority, depth);
+            } else {
+                frontier.push(link.url.clone(), priority, depth);
+            }
+        }
+
+        // Don't append unless we're testing to save memory
+        #[cfg(test)]
+        {
+            if found_urls.index && page.status == 200 {
+                all_found_urls.extend(found_urls.links.iter().map(|x| x.url.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // see issue #7
+    //use iron::{Iron, IronResult, Headers};
+    //use iron::response::Response;
+    //use iron::request::Request;
+    //use iron::status;
+    //use iron::middleware::Chain;
+    //use iron::headers::ContentType;
+    //use iron::mime::{Mime, TopLevel, SubLevel};
+    //use iron::typemap::TypeMap;
+
+    use ::*;
+
+    // see issue #7
+    //#[test]
+    //fn __main_loop() {
+    //    fn handler(req: &mut Request) -> IronResult<Response> {
+    //        let mut mime = Headers::new();
+    //        mime.set(ContentType(Mime(TopLevel::Text, SubLevel::Html, Vec::new())));
+
+    //        Ok(Response {
+    //            headers: mime,
+    //            status: Some(status::Ok),
+    //            body: Some(Box::new(match req.url.path().join("/").as_str() {
+    //                "" => "<a href='file'></a><a href='file1'></a>",
+    //                "file" => "<a href='/file1'></a>",
+    //                "file1" => "<a href='/file'></a>",
+    //                _ => "not found"
+    //            })),
+    //            extensions: TypeMap::new()
+    //        })
+    //    }
+
+    //    let child = std::thread::spawn(|| Iron::new(Chain::new(handler)).http("localhost:9999").unwrap());
+
+    //    let f: Vec<String> = Vec::new();
+    //    assert_eq!(_main_loop("http://localhost:9999/".to_string(), false), f);
+    //}
+
+    #[test]
+    fn _find_in_robot_cache() {
+        let mut fake_cache = Vec::new();
+        assert_eq!(
+            find_in_robot_cache("https://google.com", fake_cache.clone()),
+            None
+        );
+        fake_cache.push((
+            "https://google.com".to_string(),
+            RobotFileParser::new("https://google.com/robots.txt"),
+        ));
+        assert_eq!(
+            find_in_robot_cache("https://google.com", fake_cache),
+            Some((
+                "https://google.com".to_string(),
+                RobotFileParser::new("https://google.com/robots.txt")
+            ))
+        );
+    }
+
+    #[test]
+    fn _decode_text() {
+        let mut headers = reqwest::header::Headers::new();
+        assert_eq!(
+            decode_text(&headers, "héllo".as_bytes()),
+            ("héllo".to_string(), encoding_rs::UTF_8)
+        );
+
+        headers.set_raw("Content-Type", "text/html; charset=ISO-8859-1");
+        assert_eq!(
+            decode_text(&headers, b"h\xe9llo"),
+            ("héllo".to_string(), encoding_rs::WINDOWS_1252)
+        );
+
+        // the header wins over <meta>
+        headers.set_raw("Content-Type", "text/html; charset=Shift_JIS");
+        assert_eq!(
+            decode_text(&headers, b"<meta charset=utf-8>\x82\xa0").0,
+            "<meta charset=utf-8>あ"
+        );
+    }
+
+    #[test]
+    fn _request_head() {
+        assert_eq!(
+            request_head(
+                "GET",
+                &Url::parse("https://google.com:8080/search?q=a#b").unwrap()
+            ),
+            b"GET /search?q=a HTTP/1.1\r\nHost: google.com:8080\r\nAccept: */*\r\n\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn _replayed_page() {
+        let record = WarcRecord {
+            headers: vec![
+                ("WARC-Type".to_string(), "response".to_string()),
+                (
+                    "WARC-Target-URI".to_string(),
+                    "<https://google.com/>".to_string(),
+                ),
+                ("WARC-Date".to_string(), "2018-05-01T12:00:00Z".to_string()),
+            ],
+            block: b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\
+                     Transfer-Encoding: chunked\r\n\r\n5\r\n<p>a.\r\n0\r\n\r\n"
+                .to_vec(),
+        };
+
+        let page = replayed_page(&record).unwrap();
+        assert_eq!(page.url, "https://google.com/");
+        assert_eq!(page.status, 200);
+        assert_eq!(page.body, b"<p>a.".to_vec());
+        assert_eq!(page.time.to_rfc3339(), "2018-05-01T12:00:00+00:00");
+        assert_eq!(
+            page.headers.get::<reqwest::header::ContentType>(),
+            Some(&reqwest::header::ContentType::html())
+        );
+        assert!(page.headers.get_raw("Transfer-Encoding").is_none());
+        assert_eq!(page.truncated, "");
+
+        assert!(replayed_page(&WarcRecord {
+            headers: Vec::new(),
+            block: record.block.clone(),
+        })
+        .is_none());
+
+        for target in &["/relative", "<http://bad host/>", ""] {
+            let mut bad = record.clone();
+            bad.headers[1].1 = target.to_string();
+            assert!(replayed_page(&bad).is_none(), "{}", target);
+        }
+    }
+
+    #[test]
+    fn _recorded_headers() {
+        let mut headers = reqwest::header::Headers::new();
+        headers.set(reqwest::header::ContentType::html());
+        headers.set_raw("ETag", "\"abc\"");
+        headers.set_raw("X-Robots-Tag", "noarchive");
+        headers.append_raw("X-Robots-Tag", "twentiethbot: nofollow");
+
+        let recorded = recorded_headers(&headers);
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded["etag"], "\"abc\"");
+        assert_eq!(
+            recorded["x-robots-tag"],
+            "noarchive, twentiethbot: nofollow"
+        );
+    }
+
+    #[test]
+    fn _crawl_page() {
+        let handlers = Handlers::builtin();
+        let mut headers = reqwest::header::Headers::new();
+        headers.set(reqwest::header::ContentType::html());
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com",
+                &headers,
+                b"<a href='news'></a><a href='gmail'></a>",
+                None,
+                Vec::new()
+            ),
+            html::find_urls_in_html(
+                Url::parse("https://google.com/").unwrap(),
+                "<a href='news'></a><a href='gmail'></a>".to_string(),
+                Vec::new()
+            )
+        );
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com",
+                &headers,
+                b"<a href='news'></a><a href='gmail'></a>",
+                None,
+                vec!["https://google.com/news".to_string()]
+            )
+            .unwrap()
+            .links
+            .len(),
+            1
+        );
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com",
+                &reqwest::header::Headers::new(),
+                b"dummy text",
+                None,
+                Vec::new()
+            ),
+            None
+        );
+
+        headers.set_raw("X-Robots-Tag", "twentiethbot: noindex");
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com",
+                &headers,
+                b"<a href='news'></a>",
+                None,
+                Vec::new()
+            ),
+            html::find_urls_in_html(
+                Url::parse("https://google.com/").unwrap(),
+                "<a href='news'></a>".to_string(),
+                Vec::new()
+            )
+            .map(|x| CrawlResult { index: false, ..x })
+        );
+
+        // what was read while downloading is used instead of the body
+        let parsed = html::find_urls_in_html(
+            Url::parse("https://google.com/").unwrap(),
+            "<a href='news'></a><a href='gmail'></a>".to_string(),
+            Vec::new(),
+        );
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com",
+                &headers,
+                b"",
+                parsed,
+                vec!["https://google.com/news".to_string()]
+            )
+            .map(|x| (x.index, x.links.len())),
+            Some((false, 1))
+        );
+
+        let mut headers = reqwest::header::Headers::new();
+        headers.set_raw("Content-Type", "application/pdf");
+        headers.set_raw("X-Robots-Tag", "noindex");
+        assert_eq!(
+            crawl_page(
+                &handlers,
+                "https://google.com/file.pdf",
+                &headers,
+                b"%PDF-1.4",
+                None,
+                Vec::new()
+            ),
+            Some(CrawlResult {
+                index: false,
+                ..CrawlResult::new("")
+            })
+        );
+    }
+}
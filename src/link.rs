@@ -0,0 +1,203 @@
+use feed::FEED_TYPES;
+use mime_sniff;
+
+// rel values that mark a link as not endorsed by the page linking to it
+pub static NOFOLLOW_RELS: [&str; 3] = ["nofollow", "ugc", "sponsored"];
+
+// <link> rels that point to another document; any other <link> (stylesheets,
+// icons, preconnect, manifest, pingback...) is something the page uses
+static NAVIGATION_RELS: [&str; 5] = ["alternate", "canonical", "next", "prev", "feed"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    /// A link to another page, like `<a>` or `<area>`.
+    Navigation,
+    /// Something embedded in the page, like an image, script or stylesheet.
+    Resource,
+    /// Another page displayed inside this one.
+    Frame,
+}
+
+impl LinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LinkKind::Navigation => "navigation",
+            LinkKind::Resource => "resource",
+            LinkKind::Frame => "frame",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: String,
+    pub rel: Vec<String>,
+    /// The element and attribute the link was found in, such as `img` and `srcset`.
+    pub element: String,
+    pub attribute: String,
+    /// The text of an `<a>`, with whitespace collapsed.
+    pub anchor_text: String,
+    pub title: String,
+    /// The `alt` of an image inside an `<a>`, which stands in for its text.
+    pub image_alt: String,
+    /// The `type` the page gives for what's linked to, lowercased.
+    pub media_type: String,
+}
+
+impl Link {
+    pub fn new(url: String) -> Link {
+        Link {
+            url: url,
+            rel: Vec::new(),
+            element: String::new(),
+            attribute: String::new(),
+            anchor_text: String::new(),
+            title: String::new(),
+            image_alt: String::new(),
+            media_type: String::new(),
+        }
+    }
+
+    /// Whether this is a `<link rel="alternate">` to an RSS or Atom feed.
+    pub fn is_feed(&self) -> bool {
+        self.element == "link"
+            && self.rel.iter().any(|x| x == "alternate")
+            && FEED_TYPES.contains(&mime_sniff::essence(&self.media_type).as_str())
+    }
+
+    pub fn is_nofollow(&self) -> bool {
+        self.rel.iter().any(|x| NOFOLLOW_RELS.contains(&x.as_str()))
+    }
+
+    pub fn kind(&self) -> LinkKind {
+        match self.element.as_str() {
+            // url()s in a style="" are backgrounds and the like, whatever the element
+            _ if self.attribute == "style" => LinkKind::Resource,
+            "iframe" | "frame" | "amp-iframe" | "amp-embed" => LinkKind::Frame,
+            // where a script redirects to is a page, not the script
+            "script" if self.attribute == "location" => LinkKind::Navigation,
+            "img" | "script" | "source" | "embed" | "object" | "video" | "audio" | "track"
+            | "style" | "css" => LinkKind::Resource,
+            "link"
+                if self
+                    .rel
+                    .iter()
+                    .any(|x| NAVIGATION_RELS.contains(&x.as_str())) =>
+            {
+                LinkKind::Navigation
+            }
+            "link" => LinkKind::Resource,
+            element if element.starts_with("amp-") => LinkKind::Resource,
+            // a, area, form, blockquote and guessed root domains
+            _ => LinkKind::Navigation,
+        }
+    }
+}
+
+/// Splits a `rel` attribute into its lowercased, space-separated values.
+pub fn parse_rel(rel: &str) -> Vec<String> {
+    rel.split_whitespace().map(|x| x.to_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use link::*;
+
+    #[test]
+    fn _parse_rel() {
+        assert_eq!(
+            parse_rel(" noopener  NoFollow\tugc "),
+            vec![
+                "noopener".to_string(),
+                "nofollow".to_string(),
+                "ugc".to_string()
+            ]
+        );
+        assert_eq!(parse_rel(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn _is_nofollow() {
+        let mut link = Link::new("https://google.com/".to_string());
+        assert!(!link.is_nofollow());
+
+        link.rel = parse_rel("noopener");
+        assert!(!link.is_nofollow());
+
+        for rel in NOFOLLOW_RELS.iter() {
+            link.rel = parse_rel(rel);
+            assert!(link.is_nofollow());
+        }
+    }
+
+    #[test]
+    fn _is_feed() {
+        let mut link = Link {
+            element: "link".to_string(),
+            rel: parse_rel("alternate"),
+            media_type: "application/rss+xml".to_string(),
+            ..Link::new("https://google.com/feed".to_string())
+        };
+        assert!(link.is_feed());
+
+        link.media_type = "application/atom+xml; charset=utf-8".to_string();
+        assert!(link.is_feed());
+
+        link.media_type = "text/html".to_string();
+        assert!(!link.is_feed());
+
+        link.media_type = "application/rss+xml".to_string();
+        link.element = "a".to_string();
+        assert!(!link.is_feed());
+    }
+
+    #[test]
+    fn _kind() {
+        fn kind(element: &str, rel: &str) -> LinkKind {
+            Link {
+                element: element.to_string(),
+                rel: parse_rel(rel),
+                ..Link::new("https://google.com/".to_string())
+            }
+            .kind()
+        }
+
+        assert_eq!(kind("a", ""), LinkKind::Navigation);
+        assert_eq!(kind("area", "nofollow"), LinkKind::Navigation);
+        assert_eq!(kind("", ""), LinkKind::Navigation);
+        assert_eq!(kind("link", "alternate"), LinkKind::Navigation);
+        assert_eq!(kind("link", "Stylesheet"), LinkKind::Resource);
+        assert_eq!(kind("link", "shortcut icon"), LinkKind::Resource);
+        assert_eq!(kind("link", "canonical"), LinkKind::Navigation);
+        assert_eq!(kind("link", "Next"), LinkKind::Navigation);
+        assert_eq!(kind("link", "feed"), LinkKind::Navigation);
+        assert_eq!(kind("link", "preconnect"), LinkKind::Resource);
+        assert_eq!(kind("link", "dns-prefetch"), LinkKind::Resource);
+        assert_eq!(kind("link", "preload"), LinkKind::Resource);
+        assert_eq!(kind("link", "manifest"), LinkKind::Resource);
+        assert_eq!(kind("link", "pingback"), LinkKind::Resource);
+        assert_eq!(kind("link", ""), LinkKind::Resource);
+        assert_eq!(kind("img", ""), LinkKind::Resource);
+        assert_eq!(kind("script", ""), LinkKind::Resource);
+        assert_eq!(kind("video", ""), LinkKind::Resource);
+        assert_eq!(kind("amp-img", ""), LinkKind::Resource);
+        assert_eq!(kind("iframe", ""), LinkKind::Frame);
+        assert_eq!(kind("amp-iframe", ""), LinkKind::Frame);
+        assert_eq!(kind("css", ""), LinkKind::Resource);
+        assert_eq!(kind("style", ""), LinkKind::Resource);
+
+        let link = Link {
+            element: "div".to_string(),
+            attribute: "style".to_string(),
+            ..Link::new("https://google.com/bg.png".to_string())
+        };
+        assert_eq!(link.kind(), LinkKind::Resource);
+
+        let link = Link {
+            element: "script".to_string(),
+            attribute: "location".to_string(),
+            ..Link::new("https://google.com/news".to_string())
+        };
+        assert_eq!(link.kind(), LinkKind::Navigation);
+    }
+}
@@ -0,0 +1,204 @@
+use chrono::{DateTime, Utc};
+use link::Link;
+use options::Format;
+use output::{escape_field, PageRecord};
+use serde_json;
+use sink::{Finish, OutputSink};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+#[derive(Debug, Serialize)]
+struct Edge<'a> {
+    source_id: u64,
+    target_id: u64,
+    source: &'a str,
+    target: &'a str,
+    anchor: &'a str,
+    title: &'a str,
+    image_alt: &'a str,
+    rel: &'a [String],
+    element: &'a str,
+    attribute: &'a str,
+    kind: &'static str,
+    time: &'a str,
+}
+
+/// Writes one edge per link, giving every URL the same node ID for the whole run.
+pub struct LinkGraph<W: Write> {
+    writer: W,
+    format: Format,
+    node_ids: HashMap<String, u64>,
+}
+
+impl<W: Write> LinkGraph<W> {
+    pub fn new(writer: W, format: Format) -> LinkGraph<W> {
+        LinkGraph {
+            writer: writer,
+            format: format,
+            node_ids: HashMap::new(),
+        }
+    }
+
+    fn node_id(&mut self, url: &str) -> u64 {
+        if let Some(id) = self.node_ids.get(url) {
+            return *id;
+        }
+
+        let id = self.node_ids.len() as u64;
+        self.node_ids.insert(url.to_string(), id);
+        return id;
+    }
+
+    pub fn write_link(
+        &mut self,
+        source: &str,
+        link: &Link,
+        crawl_time: DateTime<Utc>,
+    ) -> io::Result<()> {
+        // guessed root domains aren't actually linked to
+        if link.element.len() == 0 {
+            return Ok(());
+        }
+
+        let time = crawl_time.to_rfc3339();
+        let edge = Edge {
+            source_id: self.node_id(source),
+            target_id: self.node_id(&link.url),
+            source: source,
+            target: &link.url,
+            anchor: &link.anchor_text,
+            title: &link.title,
+            image_alt: &link.image_alt,
+            rel: &link.rel,
+            element: &link.element,
+            attribute: &link.attribute,
+            kind: link.kind().as_str(),
+            time: &time,
+        };
+
+        match self.format {
+            Format::Tsv => writeln!(
+                self.writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                edge.source_id,
+                edge.target_id,
+                escape_field(edge.source),
+                escape_field(edge.target),
+                escape_field(edge.anchor),
+                escape_field(&edge.rel.join(" ")),
+                escape_field(edge.element),
+                edge.kind,
+                edge.time,
+                escape_field(edge.title),
+                escape_field(edge.image_alt),
+                escape_field(edge.attribute)
+            ),
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &edge)?;
+                self.writer.write_all(b"\n")
+            }
+        }
+    }
+}
+
+impl<W: Finish> OutputSink for LinkGraph<W> {
+    fn on_page(&mut self, _page: &PageRecord, _body: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_link(&mut self, source: &str, link: &Link, time: DateTime<Utc>) -> io::Result<()> {
+        self.write_link(source, link, time)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use link_graph::*;
+
+    fn write_links(graph: &mut LinkGraph<Vec<u8>>, source: &str, links: &[Link]) {
+        let time = "2018-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        for link in links {
+            graph.on_link(source, link, time).unwrap();
+        }
+        graph.flush().unwrap();
+    }
+
+    fn links() -> Vec<Link> {
+        vec![
+            Link {
+                element: "a".to_string(),
+                attribute: "href".to_string(),
+                anchor_text: "Google News".to_string(),
+                title: "News".to_string(),
+                rel: vec!["nofollow".to_string(), "ugc".to_string()],
+                ..Link::new("https://google.com/news".to_string())
+            },
+            Link::new("https://google.com/".to_string()),
+            Link {
+                element: "img".to_string(),
+                attribute: "src".to_string(),
+                image_alt: "Logo".to_string(),
+                ..Link::new("https://google.com/logo.png".to_string())
+            },
+        ]
+    }
+
+    #[test]
+    fn _write_links_tsv() {
+        let mut graph = LinkGraph::new(Vec::new(), Format::Tsv);
+        write_links(&mut graph, "https://google.com/", &links());
+        write_links(&mut graph, "https://google.com/news", &links()[..1]);
+
+        assert_eq!(
+            String::from_utf8(graph.writer).unwrap(),
+            "0\t1\thttps://google.com/\thttps://google.com/news\tGoogle News\tnofollow ugc\ta\tnavigation\t2018-05-01T12:00:00+00:00\tNews\t\thref\n\
+             0\t2\thttps://google.com/\thttps://google.com/logo.png\t\t\timg\tresource\t2018-05-01T12:00:00+00:00\t\tLogo\tsrc\n\
+             1\t1\thttps://google.com/news\thttps://google.com/news\tGoogle News\tnofollow ugc\ta\tnavigation\t2018-05-01T12:00:00+00:00\tNews\t\thref\n"
+        );
+    }
+
+    #[test]
+    fn _write_links_tsv_escaping() {
+        // Markdown link titles can have anything in them
+        let link = Link {
+            element: "a".to_string(),
+            title: "Top\tstories\nand more \\o/".to_string(),
+            ..Link::new("https://google.com/news".to_string())
+        };
+        let mut graph = LinkGraph::new(Vec::new(), Format::Tsv);
+        write_links(&mut graph, "https://google.com/", &[link]);
+
+        let output = String::from_utf8(graph.writer).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        let columns: Vec<&str> = output.trim_end_matches('\n').split('\t').collect();
+        assert_eq!(columns.len(), 12);
+        assert_eq!(columns[9], "Top\\tstories\\nand more \\\\o/");
+    }
+
+    #[test]
+    fn _write_links_jsonl() {
+        let mut graph = LinkGraph::new(Vec::new(), Format::JsonLines);
+        write_links(&mut graph, "https://google.com/", &links()[..1]);
+
+        let edge: serde_json::Value = serde_json::from_slice(&graph.writer).unwrap();
+        assert_eq!(edge["source_id"], 0);
+        assert_eq!(edge["target_id"], 1);
+        assert_eq!(edge["target"], "https://google.com/news");
+        assert_eq!(edge["anchor"], "Google News");
+        assert_eq!(edge["title"], "News");
+        assert_eq!(edge["attribute"], "href");
+        assert_eq!(edge["rel"][0], "nofollow");
+        assert_eq!(edge["rel"][1], "ugc");
+        assert_eq!(edge["element"], "a");
+        assert_eq!(edge["kind"], "navigation");
+        assert_eq!(edge["time"], "2018-05-01T12:00:00+00:00");
+    }
+}
@@ -0,0 +1,317 @@
+/// How much of a body is looked at, the spec's "resource header".
+static HEADER_BYTES: usize = 1445;
+
+// tags that mean a page is HTML, when followed by a space or >
+static HTML_PATTERNS: [&[u8]; 17] = [
+    b"<!DOCTYPE HTML",
+    b"<HTML",
+    b"<HEAD",
+    b"<SCRIPT",
+    b"<IFRAME",
+    b"<H1",
+    b"<DIV",
+    b"<FONT",
+    b"<TABLE",
+    b"<A",
+    b"<STYLE",
+    b"<TITLE",
+    b"<B",
+    b"<BODY",
+    b"<BR",
+    b"<P",
+    b"<!--",
+];
+
+// signatures that can't be mistaken for text, and what they mean
+static BINARY_SIGNATURES: [(&[u8], &str); 16] = [
+    (b"%PDF-", "application/pdf"),
+    (b"%!PS-Adobe-", "application/postscript"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"\x00\x00\x02\x00", "image/x-icon"),
+    (b"BM", "image/bmp"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS\x00", "application/ogg"),
+    (b"MThd\x00\x00\x00\x06", "audio/midi"),
+    (b"\x1f\x8b\x08", "application/x-gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"Rar!\x1a\x07\x00", "application/x-rar-compressed"),
+    (b"wOFF", "font/woff"),
+];
+
+// RIFF and FORM containers, told apart by the four bytes at offset 8
+static CONTAINER_SIGNATURES: [(&[u8], &[u8], &str); 4] = [
+    (b"RIFF", b"WEBP", "image/webp"),
+    (b"RIFF", b"AVI ", "video/avi"),
+    (b"RIFF", b"WAVE", "audio/wave"),
+    (b"FORM", b"AIFF", "audio/aiff"),
+];
+
+/// The type and subtype of a Content-Type, lowercased and without parameters.
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap()
+        .trim()
+        .to_lowercase()
+}
+
+pub fn is_html(content_type: &str) -> bool {
+    let essence = essence(content_type);
+    return essence == "text/html" || essence == "application/xhtml+xml";
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b'\t' || c == b'\n' || c == b'\x0c' || c == b'\r' || c == b' '
+}
+
+fn is_binary(c: u8) -> bool {
+    c <= 0x08 || c == 0x0b || (c >= 0x0e && c <= 0x1a) || (c >= 0x1c && c <= 0x1f)
+}
+
+// the spec's MP4 check: an ftyp box with an mp4 brand
+fn is_mp4(header: &[u8]) -> bool {
+    if header.len() < 12 || &header[4..8] != b"ftyp" {
+        return false;
+    }
+    let size = ((header[0] as usize) << 24)
+        | ((header[1] as usize) << 16)
+        | ((header[2] as usize) << 8)
+        | header[3] as usize;
+    if size > header.len() || size % 4 != 0 || size < 12 {
+        return false;
+    }
+
+    return &header[8..11] == b"mp4"
+        || (16..size)
+            .step_by(4)
+            .any(|x| x + 3 <= size && &header[x..x + 3] == b"mp4");
+}
+
+// an EBML header whose DocType is webm
+fn is_webm(header: &[u8]) -> bool {
+    if !header.starts_with(b"\x1a\x45\xdf\xa3") {
+        return false;
+    }
+
+    let end = header.len().min(38);
+    return (4..end.saturating_sub(1))
+        .filter(|x| header[*x] == 0x42 && header[*x + 1] == 0x82)
+        .any(|x| {
+            let rest = &header[x + 2..];
+            rest[..rest.len().min(12)].windows(4).any(|x| x == b"webm")
+        });
+}
+
+/// The spec's rules for identifying an unknown MIME type. Without
+/// `scriptable`, HTML and XML aren't looked for.
+fn sniff_unknown(header: &[u8], scriptable: bool) -> &'static str {
+    if scriptable {
+        let start = header
+            .iter()
+            .position(|x| !is_whitespace(*x))
+            .unwrap_or(header.len());
+        let text = &header[start..];
+
+        for pattern in HTML_PATTERNS.iter() {
+            if text.len() > pattern.len()
+                && text[..pattern.len()].eq_ignore_ascii_case(pattern)
+                && (text[pattern.len()] == b' ' || text[pattern.len()] == b'>')
+            {
+                return "text/html";
+            }
+        }
+        if text.starts_with(b"<?xml") {
+            return "text/xml";
+        }
+    }
+
+    if header.starts_with(b"\xfe\xff")
+        || header.starts_with(b"\xff\xfe")
+        || header.starts_with(b"\xef\xbb\xbf")
+    {
+        return "text/plain";
+    }
+
+    for &(signature, mime) in BINARY_SIGNATURES.iter() {
+        if header.starts_with(signature) {
+            return mime;
+        }
+    }
+    for &(container, kind, mime) in CONTAINER_SIGNATURES.iter() {
+        if header.len() >= 12 && header.starts_with(container) && &header[8..12] == kind {
+            return mime;
+        }
+    }
+    if header.starts_with(b"wOF2") {
+        return "font/woff2";
+    }
+    if is_mp4(header) {
+        return "video/mp4";
+    }
+    if is_webm(header) {
+        return "video/webm";
+    }
+
+    if header.iter().any(|x| is_binary(*x)) {
+        return "application/octet-stream";
+    }
+    return "text/plain";
+}
+
+// a missing or meaningless type, which is always sniffed
+fn is_unknown(declared_essence: &str) -> bool {
+    !declared_essence.contains('/')
+        || declared_essence == "unknown/unknown"
+        || declared_essence == "application/unknown"
+        || declared_essence == "*/*"
+}
+
+// browsers only tell text from binary for these, but they're rarely chosen on purpose
+fn is_generic(declared: &str, declared_essence: &str) -> bool {
+    declared_essence == "application/octet-stream"
+        || [
+            "text/plain",
+            "text/plain; charset=iso-8859-1",
+            "text/plain; charset=utf-8",
+        ]
+        .contains(&declared.trim().to_lowercase().as_str())
+}
+
+/// Whether `sniff` might treat a body as something other than its declared
+/// type; if not, it can be handled before any of it has arrived.
+pub fn may_sniff(declared: &str, no_sniff: bool) -> bool {
+    let declared_essence = essence(declared);
+    is_unknown(&declared_essence) || (!no_sniff && is_generic(declared, &declared_essence))
+}
+
+/// Works out what a body really is from its first bytes, as in the WHATWG MIME
+/// Sniffing standard, returning the Content-Type to treat it as. The declared
+/// type is kept unless it's missing or says nothing (`application/octet-stream`,
+/// `unknown/unknown`...), or it's one of the `text/plain`s that some servers
+/// send for everything. `no_sniff` is `X-Content-Type-Options: nosniff`, which
+/// leaves any declared type alone.
+pub fn sniff(declared: &str, body: &[u8], no_sniff: bool) -> String {
+    let header = &body[..body.len().min(HEADER_BYTES)];
+    let declared_essence = essence(declared);
+
+    if is_unknown(&declared_essence) {
+        return sniff_unknown(header, !no_sniff).to_string();
+    }
+    if no_sniff {
+        return declared.to_string();
+    }
+
+    if !is_generic(declared, &declared_essence) {
+        return declared.to_string();
+    }
+
+    let sniffed = sniff_unknown(header, true);
+    if sniffed == declared_essence {
+        return declared.to_string();
+    }
+
+    // keep the charset and any other parameters
+    return match declared.find(';') {
+        Some(parameters) => format!("{}{}", sniffed, &declared[parameters..]),
+        None => sniffed.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use mime_sniff::*;
+
+    #[test]
+    fn _essence() {
+        assert_eq!(essence("Text/HTML; charset=utf-8"), "text/html");
+        assert_eq!(essence(""), "");
+        assert!(is_html("application/xhtml+xml"));
+        assert!(!is_html("text/plain"));
+    }
+
+    #[test]
+    fn _sniff_unknown() {
+        assert_eq!(sniff_unknown(b"\n  <!doctype html>", true), "text/html");
+        assert_eq!(sniff_unknown(b"<p>hello", true), "text/html");
+        // <pre isn't <p
+        assert_eq!(sniff_unknown(b"<pre>hello", true), "text/plain");
+        assert_eq!(sniff_unknown(b"<p>hello", false), "text/plain");
+        assert_eq!(sniff_unknown(b"<?xml version='1.0'?>", true), "text/xml");
+        assert_eq!(
+            sniff_unknown(b"%PDF-1.4\n%\xe2\xe3", true),
+            "application/pdf"
+        );
+        assert_eq!(
+            sniff_unknown(b"\x89PNG\r\n\x1a\n\x00\x00", true),
+            "image/png"
+        );
+        assert_eq!(
+            sniff_unknown(b"RIFF\x00\x00\x00\x00WEBPVP8 ", true),
+            "image/webp"
+        );
+        assert_eq!(
+            sniff_unknown(
+                b"\x00\x00\x00\x1cftypisom\x00\x00\x02\x00isomiso2mp41",
+                true
+            ),
+            "video/mp4"
+        );
+        assert_eq!(
+            sniff_unknown(
+                b"\x1a\x45\xdf\xa3\x9f\x42\x86\x81\x01\x42\x82\x84webm",
+                true
+            ),
+            "video/webm"
+        );
+        assert_eq!(sniff_unknown(b"\xef\xbb\xbf\x00", true), "text/plain");
+        assert_eq!(
+            sniff_unknown(b"hello\x00", true),
+            "application/octet-stream"
+        );
+        assert_eq!(sniff_unknown(b"", true), "text/plain");
+    }
+
+    #[test]
+    fn _sniff() {
+        let html = b"<html><p>hi";
+        assert_eq!(sniff("", html, false), "text/html");
+        assert_eq!(sniff("application/octet-stream", html, false), "text/html");
+        assert_eq!(
+            sniff("text/plain; charset=UTF-8", html, false),
+            "text/html; charset=UTF-8"
+        );
+        assert_eq!(
+            sniff("text/plain; charset=UTF-8", b"hello", false),
+            "text/plain; charset=UTF-8"
+        );
+        // anything more specific is trusted
+        assert_eq!(
+            sniff("text/plain; charset=Shift_JIS", html, false),
+            "text/plain; charset=Shift_JIS"
+        );
+        assert_eq!(sniff("image/png", html, false), "image/png");
+        assert_eq!(sniff("text/css", html, false), "text/css");
+
+        assert_eq!(
+            sniff("application/octet-stream", html, true),
+            "application/octet-stream"
+        );
+        assert_eq!(sniff("", html, true), "text/plain");
+        assert_eq!(sniff("", b"%PDF-1.7", true), "application/pdf");
+    }
+
+    #[test]
+    fn _may_sniff() {
+        assert!(may_sniff("", false));
+        assert!(may_sniff("", true));
+        assert!(may_sniff("text/plain", false));
+        assert!(!may_sniff("text/plain", true));
+        assert!(!may_sniff("text/html; charset=UTF-8", false));
+        assert!(!may_sniff("text/plain; charset=Shift_JIS", false));
+    }
+}
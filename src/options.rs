@@ -0,0 +1,791 @@
+use download;
+use warc;
+
+pub static USAGE: &str = "usage: crawler [--format tsv|jsonl] [--output KIND:PATH[,status=CODE][,type=MIME]
+               [,rotate-bytes|rotate-records|rotate-seconds=N][,compress=gzip|zstd]]...
+               [--nofollow follow|deprioritize|skip]
+               [--no-sitemaps] [--navigation-links|--resource-links|--frame-links enqueue|head|record]
+               [--link-graph FILE [--link-graph-format tsv|jsonl]]
+               [--warc PREFIX [--warc-max-size BYTES]] [--sqlite FILE]
+               [--max-html-bytes BYTES] [--max-other-bytes BYTES] [--max-download-seconds N] URL
+       crawler replay [--format tsv|jsonl] [--output ...]...
+               [--link-graph FILE [--link-graph-format tsv|jsonl]] [--sqlite FILE] WARC...
+       crawler pagerank [--damping D] [--iterations N] [--tolerance T]
+               [--dangling uniform|ignore] [--hosts] [--all-links] [--output FILE] LINK_GRAPH";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NofollowPolicy {
+    Follow,
+    Deprioritize,
+    Skip,
+}
+
+impl NofollowPolicy {
+    fn parse(value: &str) -> Result<NofollowPolicy, String> {
+        match value {
+            "follow" => Ok(NofollowPolicy::Follow),
+            "deprioritize" => Ok(NofollowPolicy::Deprioritize),
+            "skip" => Ok(NofollowPolicy::Skip),
+            _ => Err(format!("unknown nofollow policy {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkPolicy {
+    /// Crawl it like any other page.
+    Enqueue,
+    /// Only send a HEAD request to check that it exists.
+    HeadOnly,
+    /// Don't fetch it at all, just keep it as one of the page's links.
+    Record,
+}
+
+impl LinkPolicy {
+    fn parse(value: &str) -> Result<LinkPolicy, String> {
+        match value {
+            "enqueue" => Ok(LinkPolicy::Enqueue),
+            "head" => Ok(LinkPolicy::HeadOnly),
+            "record" => Ok(LinkPolicy::Record),
+            _ => Err(format!("unknown link policy {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Tsv,
+    JsonLines,
+}
+
+impl Format {
+    fn parse(value: &str) -> Result<Format, String> {
+        match value {
+            "tsv" => Ok(Format::Tsv),
+            "jsonl" => Ok(Format::JsonLines),
+            _ => Err(format!("unknown format {:?}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn parse(value: &str) -> Result<Compression, String> {
+        match value {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression {:?}", value)),
+        }
+    }
+}
+
+/// When a file output moves on to its next part. Zero means no limit, and
+/// no limits at all means everything goes in one file.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rotation {
+    /// Uncompressed bytes per part.
+    pub max_bytes: u64,
+    pub max_records: u64,
+    pub max_seconds: u64,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0 || self.max_records > 0 || self.max_seconds > 0
+    }
+}
+
+/// What an output writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SinkKind {
+    Pages(Format),
+    LinkGraph(Format),
+    /// The items of every feed crawled.
+    FeedEntries(Format),
+    Sqlite,
+}
+
+/// Which pages an output gets. An empty list allows anything.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PageFilter {
+    pub statuses: Vec<u16>,
+    /// Media types, matched against the start of the sniffed Content-Type.
+    pub content_types: Vec<String>,
+}
+
+impl PageFilter {
+    pub fn matches(&self, status: u16, content_type: &str) -> bool {
+        let content_type = content_type.to_lowercase();
+
+        (self.statuses.len() == 0 || self.statuses.contains(&status))
+            && (self.content_types.len() == 0
+                || self
+                    .content_types
+                    .iter()
+                    .any(|x| content_type.starts_with(x.as_str())))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkSpec {
+    pub kind: SinkKind,
+    /// A file, or `-` for stdout.
+    pub path: String,
+    pub filter: PageFilter,
+    pub rotation: Rotation,
+    pub compression: Compression,
+}
+
+// whether part of an --output starts with NAME=, NAME being lowercase letters and dashes
+fn is_output_option(part: &str) -> bool {
+    match part.find('=') {
+        Some(equals) if equals != 0 => part[..equals]
+            .chars()
+            .all(|x| x.is_ascii_lowercase() || x == '-'),
+        _ => false,
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {:?}", name, value))
+}
+
+impl SinkSpec {
+    fn new(kind: SinkKind, path: String, filter: PageFilter) -> SinkSpec {
+        SinkSpec {
+            kind: kind,
+            path: path,
+            filter: filter,
+            rotation: Rotation::default(),
+            compression: Compression::None,
+        }
+    }
+
+    // KIND:PATH[,OPTION=VALUE]..., like jsonl:pages.jsonl,status=200,type=text/html,compress=gzip.
+    // PATH can have commas in it, as long as what follows them doesn't look like an option.
+    fn parse(value: &str) -> Result<SinkSpec, String> {
+        let colon = value
+            .find(':')
+            .ok_or(format!("expected KIND:PATH for --output, not {:?}", value))?;
+        let kind = match &value[..colon] {
+            "tsv" => SinkKind::Pages(Format::Tsv),
+            "jsonl" => SinkKind::Pages(Format::JsonLines),
+            "links-tsv" => SinkKind::LinkGraph(Format::Tsv),
+            "links-jsonl" => SinkKind::LinkGraph(Format::JsonLines),
+            "entries-tsv" => SinkKind::FeedEntries(Format::Tsv),
+            "entries-jsonl" => SinkKind::FeedEntries(Format::JsonLines),
+            "sqlite" => SinkKind::Sqlite,
+            kind => return Err(format!("unknown output kind {:?}", kind)),
+        };
+
+        let rest = &value[colon + 1..];
+        let options_start = rest
+            .match_indices(',')
+            .map(|x| x.0)
+            .find(|x| is_output_option(&rest[x + 1..]))
+            .unwrap_or(rest.len());
+        let path = rest[..options_start].to_string();
+        if path.len() == 0 || (path == "-" && kind == SinkKind::Sqlite) {
+            return Err(format!("invalid path for --output {:?}", value));
+        }
+
+        let mut spec = SinkSpec::new(kind, path, PageFilter::default());
+        for part in rest[options_start..].split(',').skip(1) {
+            match part.find('=').map(|x| (&part[..x], &part[x + 1..])) {
+                Some(("status", status)) => spec.filter.statuses.push(
+                    status
+                        .parse()
+                        .map_err(|_| format!("invalid status {:?}", status))?,
+                ),
+                Some(("type", content_type)) => {
+                    spec.filter.content_types.push(content_type.to_lowercase());
+                }
+                Some(("rotate-bytes", value)) => {
+                    spec.rotation.max_bytes = parse_number("size", value)?;
+                }
+                Some(("rotate-records", value)) => {
+                    spec.rotation.max_records = parse_number("record count", value)?;
+                }
+                Some(("rotate-seconds", value)) => {
+                    spec.rotation.max_seconds = parse_number("number of seconds", value)?;
+                }
+                Some(("compress", value)) => spec.compression = Compression::parse(value)?,
+                _ => return Err(format!("unknown output option {:?}", part)),
+            }
+        }
+
+        // only files can be split up or compressed
+        if (spec.rotation.is_enabled() || spec.compression != Compression::None)
+            && (spec.path == "-" || spec.kind == SinkKind::Sqlite)
+        {
+            return Err(format!("{:?} can't be rotated or compressed", value));
+        }
+
+        Ok(spec)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub start_url: String,
+    /// How to write crawled pages to stdout, if there are no `--output`s.
+    pub format: Format,
+    pub outputs: Vec<SinkSpec>,
+    /// What to do with links marked rel="nofollow", "ugc" or "sponsored".
+    pub nofollow: NofollowPolicy,
+    /// Whether to queue the URLs listed in each host's sitemaps.
+    pub sitemaps: bool,
+    /// What to do with links to other pages (`<a>`, `<area>`...).
+    pub navigation_links: LinkPolicy,
+    /// What to do with images, scripts, stylesheets and other media.
+    pub resource_links: LinkPolicy,
+    /// What to do with `<iframe>`s.
+    pub frame_links: LinkPolicy,
+    /// Where to write an edge list of every link found, if anywhere.
+    pub link_graph: Option<String>,
+    pub link_graph_format: Format,
+    /// Where to archive every request and response as WARC files, if anywhere.
+    pub warc: Option<String>,
+    /// How big a WARC file gets before the next one is started.
+    pub warc_max_size: u64,
+    /// An SQLite database to store pages, links and the fetch log in.
+    pub sqlite: Option<String>,
+    /// How much of an HTML page's body is read before it's cut short.
+    pub max_html_bytes: u64,
+    /// The same, for anything that isn't HTML and whose handler doesn't have its own limit.
+    pub max_other_bytes: u64,
+    pub max_download_seconds: u64,
+    /// WARC files to re-process instead of crawling, for `crawler replay`.
+    pub replay: Vec<String>,
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+        Options::parse_args(args, false)
+    }
+
+    /// Parses the options for `crawler replay`, which takes WARC files instead of a URL.
+    pub fn parse_replay<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+        Options::parse_args(args, true)
+    }
+
+    fn parse_args<I: Iterator<Item = String>>(
+        mut args: I,
+        replay: bool,
+    ) -> Result<Options, String> {
+        let mut start_url = None;
+        let mut replay_files = Vec::new();
+        let mut format = Format::Tsv;
+        let mut outputs = Vec::new();
+        let mut nofollow = NofollowPolicy::Deprioritize;
+        let mut sitemaps = true;
+        let mut navigation_links = LinkPolicy::Enqueue;
+        let mut resource_links = LinkPolicy::Record;
+        let mut frame_links = LinkPolicy::Enqueue;
+        let mut link_graph = None;
+        let mut link_graph_format = Format::Tsv;
+        let mut warc = None;
+        let mut warc_max_size = warc::DEFAULT_MAX_SIZE;
+        let mut sqlite = None;
+        let mut max_html_bytes = download::DEFAULT_MAX_HTML_BYTES;
+        let mut max_other_bytes = download::DEFAULT_MAX_OTHER_BYTES;
+        let mut max_download_seconds = download::DEFAULT_MAX_SECONDS;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    format = Format::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--output" => {
+                    outputs.push(SinkSpec::parse(&value_for(&arg, args.next())?)?);
+                }
+                "--nofollow" => {
+                    nofollow = NofollowPolicy::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--no-sitemaps" => {
+                    sitemaps = false;
+                }
+                "--navigation-links" => {
+                    navigation_links = LinkPolicy::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--resource-links" => {
+                    resource_links = LinkPolicy::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--frame-links" => {
+                    frame_links = LinkPolicy::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--link-graph" => {
+                    link_graph = Some(value_for(&arg, args.next())?);
+                }
+                "--link-graph-format" => {
+                    link_graph_format = Format::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--warc" => {
+                    warc = Some(value_for(&arg, args.next())?);
+                }
+                "--warc-max-size" => {
+                    warc_max_size = number_for(&arg, args.next())?;
+                }
+                "--sqlite" => {
+                    sqlite = Some(value_for(&arg, args.next())?);
+                }
+                "--max-html-bytes" => {
+                    max_html_bytes = number_for(&arg, args.next())?;
+                }
+                "--max-other-bytes" => {
+                    max_other_bytes = number_for(&arg, args.next())?;
+                }
+                "--max-download-seconds" => {
+                    max_download_seconds = number_for(&arg, args.next())?;
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option {}", arg));
+                }
+                _ if replay => {
+                    replay_files.push(arg);
+                }
+                _ => {
+                    if start_url != None {
+                        return Err("too many arguments".to_string());
+                    }
+                    start_url = Some(arg);
+                }
+            }
+        }
+
+        if replay {
+            if replay_files.len() == 0 {
+                return Err("not enough arguments".to_string());
+            }
+            start_url = Some(String::new());
+        }
+
+        Ok(Options {
+            start_url: start_url.ok_or("not enough arguments".to_string())?,
+            format: format,
+            outputs: outputs,
+            nofollow: nofollow,
+            sitemaps: sitemaps,
+            navigation_links: navigation_links,
+            resource_links: resource_links,
+            frame_links: frame_links,
+            link_graph: link_graph,
+            link_graph_format: link_graph_format,
+            warc: warc,
+            warc_max_size: warc_max_size,
+            sqlite: sqlite,
+            max_html_bytes: max_html_bytes,
+            max_other_bytes: max_other_bytes,
+            max_download_seconds: max_download_seconds,
+            replay: replay_files,
+        })
+    }
+
+    /// Every output to write to: the `--output`s (or stdout, if there aren't
+    /// any), then `--link-graph` and `--sqlite`.
+    pub fn sinks(&self) -> Vec<SinkSpec> {
+        let mut sinks = self.outputs.clone();
+        if sinks.len() == 0 {
+            sinks.push(SinkSpec::new(
+                SinkKind::Pages(self.format),
+                "-".to_string(),
+                PageFilter {
+                    statuses: vec![200],
+                    content_types: Vec::new(),
+                },
+            ));
+        }
+
+        if let Some(ref path) = self.link_graph {
+            sinks.push(SinkSpec::new(
+                SinkKind::LinkGraph(self.link_graph_format),
+                path.clone(),
+                PageFilter::default(),
+            ));
+        }
+        if let Some(ref path) = self.sqlite {
+            sinks.push(SinkSpec::new(
+                SinkKind::Sqlite,
+                path.clone(),
+                PageFilter::default(),
+            ));
+        }
+
+        return sinks;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DanglingPolicy {
+    /// Spread the score of pages without links evenly over every page.
+    Uniform,
+    /// Let it leak out of the graph.
+    Ignore,
+}
+
+impl DanglingPolicy {
+    fn parse(value: &str) -> Result<DanglingPolicy, String> {
+        match value {
+            "uniform" => Ok(DanglingPolicy::Uniform),
+            "ignore" => Ok(DanglingPolicy::Ignore),
+            _ => Err(format!("unknown dangling node policy {:?}", value)),
+        }
+    }
+}
+
+/// Options for `crawler pagerank`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRankOptions {
+    /// The link graph to read, written by `--link-graph` in either format.
+    pub input: String,
+    pub output: Option<String>,
+    pub damping: f64,
+    pub iterations: usize,
+    /// Stop early once scores change by less than this in total.
+    pub tolerance: f64,
+    pub dangling: DanglingPolicy,
+    /// Rank hosts instead of individual URLs.
+    pub hosts: bool,
+    /// Count every link, rather than only navigation links that aren't nofollow.
+    pub all_links: bool,
+}
+
+impl PageRankOptions {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<PageRankOptions, String> {
+        let mut input = None;
+        let mut options = PageRankOptions {
+            input: String::new(),
+            output: None,
+            damping: 0.85,
+            iterations: 100,
+            tolerance: 1e-6,
+            dangling: DanglingPolicy::Uniform,
+            hosts: false,
+            all_links: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => {
+                    options.output = Some(value_for(&arg, args.next())?);
+                }
+                "--damping" => {
+                    options.damping = number_for(&arg, args.next())?;
+                    // written so NaN fails too
+                    if !(0.0..1.0).contains(&options.damping) {
+                        return Err("--damping must be at least 0 and less than 1".to_string());
+                    }
+                }
+                "--iterations" => {
+                    options.iterations = number_for(&arg, args.next())?;
+                }
+                "--tolerance" => {
+                    options.tolerance = number_for(&arg, args.next())?;
+                    if !(options.tolerance > 0.0 && options.tolerance.is_finite()) {
+                        return Err("--tolerance must be a number above 0".to_string());
+                    }
+                }
+                "--dangling" => {
+                    options.dangling = DanglingPolicy::parse(&value_for(&arg, args.next())?)?;
+                }
+                "--hosts" => {
+                    options.hosts = true;
+                }
+                "--all-links" => {
+                    options.all_links = true;
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option {}", arg));
+                }
+                _ => {
+                    if input != None {
+                        return Err("too many arguments".to_string());
+                    }
+                    input = Some(arg);
+                }
+            }
+        }
+
+        options.input = input.ok_or("not enough arguments".to_string())?;
+        Ok(options)
+    }
+}
+
+fn number_for<T: ::std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value_for(option, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid number {:?} for {}", value, option))
+}
+
+fn value_for(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("missing value for {}", option))
+}
+
+#[cfg(test)]
+mod tests {
+    use options::*;
+
+    fn args(input: &[&str]) -> ::std::vec::IntoIter<String> {
+        input
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn _parse() {
+        let options = Options::parse(args(&["https://google.com"])).unwrap();
+        assert_eq!(options.start_url, "https://google.com");
+        assert_eq!(options.format, Format::Tsv);
+        assert_eq!(options.nofollow, NofollowPolicy::Deprioritize);
+        assert_eq!(options.sitemaps, true);
+        assert_eq!(options.navigation_links, LinkPolicy::Enqueue);
+        assert_eq!(options.resource_links, LinkPolicy::Record);
+        assert_eq!(options.frame_links, LinkPolicy::Enqueue);
+        assert_eq!(options.link_graph, None);
+        assert_eq!(options.link_graph_format, Format::Tsv);
+        assert_eq!(options.warc, None);
+        assert_eq!(options.warc_max_size, warc::DEFAULT_MAX_SIZE);
+        assert_eq!(options.sqlite, None);
+        assert_eq!(options.max_html_bytes, download::DEFAULT_MAX_HTML_BYTES);
+        assert_eq!(options.max_other_bytes, download::DEFAULT_MAX_OTHER_BYTES);
+        assert_eq!(options.max_download_seconds, download::DEFAULT_MAX_SECONDS);
+
+        let options = Options::parse(args(&[
+            "--nofollow",
+            "skip",
+            "https://google.com",
+            "--no-sitemaps",
+            "--resource-links",
+            "head",
+            "--frame-links",
+            "record",
+            "--link-graph",
+            "links.jsonl",
+            "--link-graph-format",
+            "jsonl",
+            "--format",
+            "jsonl",
+            "--warc",
+            "crawl",
+            "--warc-max-size",
+            "1000",
+            "--sqlite",
+            "crawl.db",
+            "--max-html-bytes",
+            "5000000",
+            "--max-other-bytes",
+            "0",
+            "--max-download-seconds",
+            "10",
+        ]))
+        .unwrap();
+        assert_eq!(options.start_url, "https://google.com");
+        assert_eq!(options.format, Format::JsonLines);
+        assert_eq!(options.nofollow, NofollowPolicy::Skip);
+        assert_eq!(options.sitemaps, false);
+        assert_eq!(options.resource_links, LinkPolicy::HeadOnly);
+        assert_eq!(options.frame_links, LinkPolicy::Record);
+        assert_eq!(options.link_graph, Some("links.jsonl".to_string()));
+        assert_eq!(options.link_graph_format, Format::JsonLines);
+        assert_eq!(options.warc, Some("crawl".to_string()));
+        assert_eq!(options.warc_max_size, 1000);
+        assert_eq!(options.sqlite, Some("crawl.db".to_string()));
+        assert_eq!(options.max_html_bytes, 5000000);
+        assert_eq!(options.max_other_bytes, 0);
+        assert_eq!(options.max_download_seconds, 10);
+
+        assert!(Options::parse(args(&[])).is_err());
+        assert!(Options::parse(args(&["https://google.com", "https://bing.com"])).is_err());
+        assert!(Options::parse(args(&["--nofollow", "maybe", "https://google.com"])).is_err());
+        assert!(Options::parse(args(&["--frame-links", "maybe", "https://google.com"])).is_err());
+        assert!(Options::parse(args(&["https://google.com", "--nofollow"])).is_err());
+        assert!(
+            Options::parse(args(&["--link-graph-format", "csv", "https://google.com"])).is_err()
+        );
+    }
+    #[test]
+    fn _parse_outputs() {
+        let options = Options::parse(args(&[
+            "--output",
+            "tsv:-",
+            "--output",
+            "jsonl:pages.jsonl,status=200,status=203,type=Text/HTML",
+            "--output",
+            "links-tsv:links.tsv,rotate-records=100000,rotate-seconds=3600,compress=zstd",
+            "--output",
+            "entries-jsonl:entries.jsonl",
+            "--output",
+            "tsv:out,2018/pages,v2.tsv,status=200",
+            "--sqlite",
+            "crawl.db",
+            "https://google.com",
+        ]))
+        .unwrap();
+        assert_eq!(
+            options.sinks(),
+            vec![
+                SinkSpec::new(
+                    SinkKind::Pages(Format::Tsv),
+                    "-".to_string(),
+                    PageFilter::default(),
+                ),
+                SinkSpec::new(
+                    SinkKind::Pages(Format::JsonLines),
+                    "pages.jsonl".to_string(),
+                    PageFilter {
+                        statuses: vec![200, 203],
+                        content_types: vec!["text/html".to_string()],
+                    },
+                ),
+                SinkSpec {
+                    kind: SinkKind::LinkGraph(Format::Tsv),
+                    path: "links.tsv".to_string(),
+                    filter: PageFilter::default(),
+                    rotation: Rotation {
+                        max_bytes: 0,
+                        max_records: 100000,
+                        max_seconds: 3600,
+                    },
+                    compression: Compression::Zstd,
+                },
+                SinkSpec::new(
+                    SinkKind::FeedEntries(Format::JsonLines),
+                    "entries.jsonl".to_string(),
+                    PageFilter::default(),
+                ),
+                SinkSpec::new(
+                    SinkKind::Pages(Format::Tsv),
+                    "out,2018/pages,v2.tsv".to_string(),
+                    PageFilter {
+                        statuses: vec![200],
+                        content_types: Vec::new(),
+                    },
+                ),
+                SinkSpec::new(
+                    SinkKind::Sqlite,
+                    "crawl.db".to_string(),
+                    PageFilter::default(),
+                ),
+            ]
+        );
+
+        // without --output, pages go to stdout
+        let sinks = Options::parse(args(&["--link-graph", "links.tsv", "https://google.com"]))
+            .unwrap()
+            .sinks();
+        assert_eq!(sinks.len(), 2);
+        assert_eq!(sinks[0].kind, SinkKind::Pages(Format::Tsv));
+        assert_eq!(sinks[0].path, "-");
+        assert_eq!(sinks[1].kind, SinkKind::LinkGraph(Format::Tsv));
+
+        for output in &[
+            "pages.tsv",
+            "csv:pages.csv",
+            "tsv:",
+            "sqlite:-",
+            "tsv:-,status=ok",
+            "tsv:-,lang=en",
+            "tsv:-,compress=gzip",
+            "sqlite:crawl.db,rotate-bytes=1000",
+            "tsv:pages.tsv,compress=xz",
+            "tsv:pages.tsv,rotate-records=many",
+            "tsv:pages.tsv,status=200,",
+        ] {
+            assert!(Options::parse(args(&["--output", output, "https://google.com"])).is_err());
+        }
+    }
+
+    #[test]
+    fn _page_filter() {
+        let filter = PageFilter {
+            statuses: vec![200],
+            content_types: vec!["text/html".to_string()],
+        };
+        assert!(filter.matches(200, "text/html"));
+        assert!(filter.matches(200, "Text/HTML; charset=utf-8"));
+        assert!(!filter.matches(404, "text/html"));
+        assert!(!filter.matches(200, "application/pdf"));
+        assert!(PageFilter::default().matches(500, ""));
+    }
+
+    #[test]
+    fn _parse_replay() {
+        let options =
+            Options::parse_replay(args(&["a.warc.gz", "--format", "jsonl", "b.warc"])).unwrap();
+        assert_eq!(
+            options.replay,
+            vec!["a.warc.gz".to_string(), "b.warc".to_string()]
+        );
+        assert_eq!(options.format, Format::JsonLines);
+        assert_eq!(options.start_url, "");
+
+        assert_eq!(
+            Options::parse(args(&["https://google.com"]))
+                .unwrap()
+                .replay,
+            Vec::<String>::new()
+        );
+        assert!(Options::parse_replay(args(&["--format", "jsonl"])).is_err());
+    }
+
+    #[test]
+    fn _parse_pagerank() {
+        let options = PageRankOptions::parse(args(&["links.tsv"])).unwrap();
+        assert_eq!(
+            options,
+            PageRankOptions {
+                input: "links.tsv".to_string(),
+                output: None,
+                damping: 0.85,
+                iterations: 100,
+                tolerance: 1e-6,
+                dangling: DanglingPolicy::Uniform,
+                hosts: false,
+                all_links: false,
+            }
+        );
+
+        let options = PageRankOptions::parse(args(&[
+            "--damping",
+            "0.5",
+            "--iterations",
+            "10",
+            "--dangling",
+            "ignore",
+            "--hosts",
+            "--all-links",
+            "--output",
+            "ranks.tsv",
+            "links.tsv",
+        ]))
+        .unwrap();
+        assert_eq!(options.damping, 0.5);
+        assert_eq!(options.iterations, 10);
+        assert_eq!(options.dangling, DanglingPolicy::Ignore);
+        assert!(options.hosts);
+        assert!(options.all_links);
+        assert_eq!(options.output, Some("ranks.tsv".to_string()));
+
+        assert!(PageRankOptions::parse(args(&[])).is_err());
+        assert!(PageRankOptions::parse(args(&["--damping", "1.5", "links.tsv"])).is_err());
+        assert!(PageRankOptions::parse(args(&["--damping", "NaN", "links.tsv"])).is_err());
+        for tolerance in &["0", "-1", "NaN", "inf"] {
+            assert!(
+                PageRankOptions::parse(args(&["--tolerance", tolerance, "links.tsv"])).is_err()
+            );
+        }
+        assert!(PageRankOptions::parse(args(&["--iterations", "many", "links.tsv"])).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use document::{PageDocument, PageRedirect};
+use options::Format;
+use serde_json;
+use sink::{Finish, OutputSink};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// Escapes a TSV field so it can't spill into the next field or line.
+pub fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+
+    return escaped;
+}
+
+// escapes a key or value in a `key=value;key=value` list
+fn escape_list_item(item: &str) -> String {
+    let mut escaped = String::with_capacity(item.len());
+    for c in item.chars() {
+        if c == '\\' || c == ';' || c == '=' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    return escaped;
+}
+
+pub fn join_pairs<'a, I: Iterator<Item = (&'a str, &'a str)>>(pairs: I) -> String {
+    pairs
+        .map(|(key, value)| format!("{}={}", escape_list_item(key), escape_list_item(value)))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+/// Everything written out about a crawled page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageRecord {
+    pub url: String,
+    /// Where the request ended up after following redirects.
+    pub final_url: String,
+    /// Where the page itself redirects to, with a refresh `<meta>` or a script.
+    pub redirect: Option<PageRedirect>,
+    pub status: u16,
+    /// The Content-Type the server sent.
+    pub content_type: String,
+    /// What the body turned out to be, see `mime_sniff::sniff`.
+    pub sniffed_type: String,
+    /// The encoding the body was decoded from.
+    pub encoding: String,
+    /// Why the body was cut short (`length` or `time`), or empty if it wasn't.
+    pub truncated: String,
+    pub depth: u32,
+    /// When the page was fetched, in RFC 3339.
+    pub fetch_time: String,
+    /// The response headers worth keeping, lowercased.
+    pub headers: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub document: PageDocument,
+    pub meta: Vec<(String, String)>,
+}
+
+impl PageRecord {
+    /// Formats the record as one line of tab-separated values:
+    /// `url final_url status content_type sniffed_type encoding truncated depth
+    /// fetch_time headers title description canonical lang word_count headings text
+    /// meta redirect redirect_delay`. Headers are `name=value;...`,
+    /// headings `h1=text;h2=text` and meta `name=content;...`; both redirect
+    /// fields are empty if the page doesn't redirect.
+    pub fn to_tsv(&self) -> String {
+        let document = &self.document;
+        let headings: Vec<(String, &str)> = document
+            .headings
+            .iter()
+            .map(|x| (format!("h{}", x.level), x.text.as_str()))
+            .collect();
+
+        let fields = [
+            self.url.clone(),
+            self.final_url.clone(),
+            self.status.to_string(),
+            self.content_type.clone(),
+            self.sniffed_type.clone(),
+            self.encoding.clone(),
+            self.truncated.clone(),
+            self.depth.to_string(),
+            self.fetch_time.clone(),
+            join_pairs(self.headers.iter().map(|x| (x.0.as_str(), x.1.as_str()))),
+            document.title.clone(),
+            document.description.clone(),
+            document.canonical.clone(),
+            document.lang.clone(),
+            document.word_count.to_string(),
+            join_pairs(headings.iter().map(|x| (x.0.as_str(), x.1))),
+            document.text.clone(),
+            join_pairs(self.meta.iter().map(|x| (x.0.as_str(), x.1.as_str()))),
+            // newer columns go last, so older readers still find everything else
+            self.redirect
+                .as_ref()
+                .map_or(String::new(), |x| x.url.clone()),
+            self.redirect
+                .as_ref()
+                .map_or(String::new(), |x| x.delay.to_string()),
+        ];
+
+        fields
+            .iter()
+            .map(|x| escape_field(x))
+            .collect::<Vec<String>>()
+            .join("\t")
+    }
+}
+
+pub fn write_record<W: Write>(
+    writer: &mut W,
+    record: &PageRecord,
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Tsv => writeln!(writer, "{}", record.to_tsv()),
+        Format::JsonLines => {
+            serde_json::to_writer(&mut *writer, record)?;
+            writer.write_all(b"\n")
+        }
+    }
+}
+
+/// Writes pages to a file or stdout.
+pub struct PageWriter<W: Write> {
+    writer: W,
+    format: Format,
+}
+
+impl<W: Write> PageWriter<W> {
+    pub fn new(writer: W, format: Format) -> PageWriter<W> {
+        PageWriter {
+            writer: writer,
+            format: format,
+        }
+    }
+}
+
+impl<W: Finish> OutputSink for PageWriter<W> {
+    fn on_page(&mut self, page: &PageRecord, _body: &[u8]) -> io::Result<()> {
+        write_record(&mut self.writer, page, self.format)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use output::*;
+    use document::Heading;
+
+    #[test]
+    fn _escape_field() {
+        assert_eq!(escape_field("a\tb\r\nc\\d"), "a\\tb\\r\\nc\\\\d");
+        assert_eq!(escape_field("plain"), "plain");
+    }
+
+    #[test]
+    fn _join_pairs() {
+        assert_eq!(
+            join_pairs(vec![("a", "1"), ("b=c", "x;y\\z")].into_iter()),
+            "a=1;b\\=c=x\\;y\\\\z"
+        );
+        assert_eq!(join_pairs(Vec::new().into_iter()), "");
+    }
+
+    fn record() -> PageRecord {
+        let mut headers = BTreeMap::new();
+        headers.insert("etag".to_string(), "\"abc\"".to_string());
+
+        PageRecord {
+            url: "https://google.com".to_string(),
+            final_url: "https://google.com/".to_string(),
+            redirect: Some(PageRedirect {
+                url: "https://google.com/news".to_string(),
+                delay: 5,
+            }),
+            status: 200,
+            content_type: "text/plain".to_string(),
+            sniffed_type: "text/html".to_string(),
+            encoding: "Shift_JIS".to_string(),
+            truncated: "length".to_string(),
+            depth: 1,
+            fetch_time: "2018-05-01T12:00:00+00:00".to_string(),
+            headers: headers,
+            document: PageDocument {
+                title: "Google".to_string(),
+                description: "Search\tthe web".to_string(),
+                canonical: "https://google.com/".to_string(),
+                lang: "en".to_string(),
+                headings: vec![Heading {
+                    level: 2,
+                    text: "News; weather".to_string(),
+                }],
+                text: "News; weather\nSports".to_string(),
+                word_count: 3,
+            },
+            meta: vec![("robots".to_string(), "index".to_string())],
+        }
+    }
+
+    #[test]
+    fn _to_tsv() {
+        assert_eq!(
+            record().to_tsv(),
+            "https://google.com\thttps://google.com/\t200\ttext/plain\ttext/html\tShift_JIS\tlength\t1\t2018-05-01T12:00:00+00:00\t\
+             etag=\"abc\"\tGoogle\tSearch\\tthe web\thttps://google.com/\ten\t3\t\
+             h2=News\\\\; weather\tNews; weather\\nSports\trobots=index\t\
+             https://google.com/news\t5"
+        );
+    }
+
+    #[test]
+    fn _write_record() {
+        let mut output = Vec::new();
+        write_record(&mut output, &record(), Format::Tsv).unwrap();
+        {
+            let mut writer = PageWriter::new(&mut output, Format::JsonLines);
+            writer.on_page(&record(), b"").unwrap();
+            writer.flush().unwrap();
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], record().to_tsv());
+
+        let page: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(page["url"], "https://google.com");
+        assert_eq!(page["final_url"], "https://google.com/");
+        assert_eq!(page["redirect"]["url"], "https://google.com/news");
+        assert_eq!(page["redirect"]["delay"], 5);
+        assert_eq!(page["status"], 200);
+        assert_eq!(page["content_type"], "text/plain");
+        assert_eq!(page["sniffed_type"], "text/html");
+        assert_eq!(page["encoding"], "Shift_JIS");
+        assert_eq!(page["truncated"], "length");
+        assert_eq!(page["depth"], 1);
+        assert_eq!(page["fetch_time"], "2018-05-01T12:00:00+00:00");
+        assert_eq!(page["headers"]["etag"], "\"abc\"");
+        assert_eq!(page["title"], "Google");
+        assert_eq!(page["headings"][0]["level"], 2);
+        assert_eq!(page["text"], "News; weather\nSports");
+        assert_eq!(page["meta"][0][1], "index");
+    }
+}
@@ -0,0 +1,309 @@
+use link::NOFOLLOW_RELS;
+use options::{DanglingPolicy, PageRankOptions};
+use serde_json;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use url::Url;
+
+#[derive(Debug, Deserialize)]
+struct Edge {
+    source: String,
+    target: String,
+    // missing from graphs written before they were recorded
+    #[serde(default)]
+    rel: Vec<String>,
+    #[serde(default)]
+    kind: String,
+}
+
+impl Edge {
+    // whether the source vouches for the target: a navigation link that isn't nofollow
+    fn is_endorsement(&self) -> bool {
+        (self.kind.len() == 0 || self.kind == "navigation")
+            && !self.rel.iter().any(|x| NOFOLLOW_RELS.contains(&x.as_str()))
+    }
+}
+
+/// A link graph in compressed sparse row form, indexed by target so each
+/// iteration can pull from a node's incoming links.
+#[derive(Debug, PartialEq)]
+pub struct Graph {
+    pub nodes: Vec<String>,
+    // the incoming links of node i are sources[offsets[i]..offsets[i + 1]]
+    offsets: Vec<usize>,
+    sources: Vec<u32>,
+    out_degree: Vec<u32>,
+}
+
+impl Graph {
+    pub fn from_edges(nodes: Vec<String>, edges: &[(u32, u32)]) -> Graph {
+        let mut offsets = vec![0; nodes.len() + 1];
+        let mut out_degree = vec![0; nodes.len()];
+
+        for &(source, target) in edges {
+            offsets[target as usize + 1] += 1;
+            out_degree[source as usize] += 1;
+        }
+        for i in 0..nodes.len() {
+            offsets[i + 1] += offsets[i];
+        }
+
+        let mut next = offsets.clone();
+        let mut sources = vec![0; edges.len()];
+        for &(source, target) in edges {
+            sources[next[target as usize]] = source;
+            next[target as usize] += 1;
+        }
+
+        Graph {
+            nodes: nodes,
+            offsets: offsets,
+            sources: sources,
+            out_degree: out_degree,
+        }
+    }
+
+    /// Reads a link graph written by `--link-graph`, in either format. With `hosts`,
+    /// every URL is replaced by its host and links within a host are left out.
+    /// Unless `all_links` is set, only navigation links that aren't nofollow count.
+    pub fn read<R: BufRead>(reader: R, hosts: bool, all_links: bool) -> io::Result<Graph> {
+        let mut node_ids: HashMap<String, u32> = HashMap::new();
+        let mut edges = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let edge = match parse_edge(&line) {
+                Some(edge) => edge,
+                None => {
+                    if line.trim().len() != 0 {
+                        warn!("ignoring invalid link graph line {:?}", line);
+                    }
+                    continue;
+                }
+            };
+            if !all_links && !edge.is_endorsement() {
+                continue;
+            }
+
+            let (source, target) = if hosts {
+                match (host_of(&edge.source), host_of(&edge.target)) {
+                    (Some(source), Some(target)) => (source, target),
+                    _ => continue,
+                }
+            } else {
+                (edge.source, edge.target)
+            };
+
+            if hosts && source == target {
+                continue;
+            }
+
+            let mut ids = [0; 2];
+            for (i, node) in vec![source, target].into_iter().enumerate() {
+                let next_id = node_ids.len() as u32;
+                ids[i] = *node_ids.entry(node).or_insert(next_id);
+            }
+
+            edges.push((ids[0], ids[1]));
+        }
+
+        // each URL is only kept once, by moving it out of the map
+        let mut nodes = vec![String::new(); node_ids.len()];
+        for (node, id) in node_ids {
+            nodes[id as usize] = node;
+        }
+
+        debug!("read {} nodes and {} edges", nodes.len(), edges.len());
+        return Ok(Graph::from_edges(nodes, &edges));
+    }
+}
+
+fn parse_edge(line: &str) -> Option<Edge> {
+    if line.starts_with("{") {
+        return serde_json::from_str(line).ok();
+    }
+
+    // source_id target_id source target anchor rel element kind ...
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.len() < 4 {
+        return None;
+    }
+    return Some(Edge {
+        source: columns[2].to_string(),
+        target: columns[3].to_string(),
+        rel: columns.get(5).map_or(Vec::new(), |x| {
+            x.split_whitespace().map(|x| x.to_string()).collect()
+        }),
+        kind: columns.get(7).unwrap_or(&"").to_string(),
+    });
+}
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_string()))
+}
+
+pub fn pagerank(
+    graph: &Graph,
+    damping: f64,
+    iterations: usize,
+    tolerance: f64,
+    dangling: DanglingPolicy,
+) -> Vec<f64> {
+    let count = graph.nodes.len();
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let mut ranks = vec![1.0 / count as f64; count];
+    let mut next = vec![0.0; count];
+
+    for iteration in 0..iterations {
+        let dangling_rank: f64 = match dangling {
+            DanglingPolicy::Uniform => (0..count)
+                .filter(|x| graph.out_degree[*x] == 0)
+                .map(|x| ranks[x])
+                .sum(),
+            DanglingPolicy::Ignore => 0.0,
+        };
+        let base = (1.0 - damping) / count as f64 + damping * dangling_rank / count as f64;
+
+        let mut change = 0.0;
+        for node in 0..count {
+            let incoming: f64 = graph.sources[graph.offsets[node]..graph.offsets[node + 1]]
+                .iter()
+                .map(|x| ranks[*x as usize] / graph.out_degree[*x as usize] as f64)
+                .sum();
+
+            next[node] = base + damping * incoming;
+            change += (next[node] - ranks[node]).abs();
+        }
+
+        ::std::mem::swap(&mut ranks, &mut next);
+
+        trace!("pagerank iteration {} changed by {}", iteration, change);
+        if change < tolerance {
+            debug!("pagerank converged after {} iterations", iteration + 1);
+            break;
+        }
+    }
+
+    return ranks;
+}
+
+/// Runs `crawler pagerank`, writing `url \t score` lines from highest to lowest score.
+pub fn run(options: &PageRankOptions) -> io::Result<()> {
+    let graph = Graph::read(
+        BufReader::new(File::open(&options.input)?),
+        options.hosts,
+        options.all_links,
+    )?;
+    let ranks = pagerank(
+        &graph,
+        options.damping,
+        options.iterations,
+        options.tolerance,
+        options.dangling,
+    );
+
+    let mut order: Vec<usize> = (0..ranks.len()).collect();
+    order.sort_by(|a, b| ranks[*b].partial_cmp(&ranks[*a]).unwrap_or(Ordering::Equal));
+
+    let mut output: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    for node in order {
+        writeln!(output, "{}\t{}", graph.nodes[node], ranks[node])?;
+    }
+
+    return output.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use pagerank::*;
+
+    fn nodes(count: usize) -> Vec<String> {
+        (0..count).map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn _from_edges() {
+        let graph = Graph::from_edges(nodes(3), &[(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(graph.offsets, vec![0, 0, 1, 3]);
+        assert_eq!(graph.sources, vec![0, 0, 1]);
+        assert_eq!(graph.out_degree, vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn _read() {
+        let input = "0\t1\thttps://a.com/\thttps://b.com/x\tB\t\ta\tnavigation\t2018-05-01T12:00:00+00:00\n\
+                     \n\
+                     {\"source_id\":1,\"target_id\":2,\"source\":\"https://b.com/x\",\"target\":\"https://b.com/y\"}\n\
+                     0\t2\thttps://a.com/\thttps://b.com/y\t\t\ta\tnavigation\t2018-05-01T12:00:00+00:00\n\
+                     0\t3\thttps://a.com/\thttps://a.com/logo.png\t\t\timg\tresource\t2018-05-01T12:00:00+00:00\n\
+                     {\"source\":\"https://b.com/y\",\"target\":\"https://c.com/\",\"rel\":[\"ugc\",\"nofollow\"],\"kind\":\"navigation\"}\n";
+
+        let graph = Graph::read(input.as_bytes(), false, false).unwrap();
+        assert_eq!(
+            graph,
+            Graph::from_edges(
+                vec![
+                    "https://a.com/".to_string(),
+                    "https://b.com/x".to_string(),
+                    "https://b.com/y".to_string(),
+                ],
+                &[(0, 1), (1, 2), (0, 2)]
+            )
+        );
+
+        let graph = Graph::read(input.as_bytes(), true, false).unwrap();
+        assert_eq!(
+            graph,
+            Graph::from_edges(
+                vec!["a.com".to_string(), "b.com".to_string()],
+                &[(0, 1), (0, 1)]
+            )
+        );
+
+        // images and nofollow links only count when asked for
+        let graph = Graph::read(input.as_bytes(), false, true).unwrap();
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.out_degree, vec![3, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn _pagerank() {
+        // a cycle ranks every node the same
+        let graph = Graph::from_edges(nodes(3), &[(0, 1), (1, 2), (2, 0)]);
+        for rank in pagerank(&graph, 0.85, 100, 1e-9, DanglingPolicy::Uniform) {
+            assert!((rank - 1.0 / 3.0).abs() < 1e-6);
+        }
+
+        // 0 and 1 both link to 2, which has no links of its own
+        let graph = Graph::from_edges(nodes(3), &[(0, 2), (1, 2)]);
+        let ranks = pagerank(&graph, 0.85, 100, 1e-9, DanglingPolicy::Uniform);
+        assert!((ranks.iter().sum::<f64>() - 1.0).abs() < 1e-6);
+        assert!((ranks[0] - ranks[1]).abs() < 1e-9);
+        assert!(ranks[2] > ranks[0]);
+
+        let ranks = pagerank(&graph, 0.85, 100, 1e-9, DanglingPolicy::Ignore);
+        assert!(ranks.iter().sum::<f64>() < 1.0);
+        assert!((ranks[0] - 0.05).abs() < 1e-9);
+
+        assert_eq!(
+            pagerank(
+                &Graph::from_edges(Vec::new(), &[]),
+                0.85,
+                100,
+                1e-9,
+                DanglingPolicy::Uniform
+            ),
+            Vec::<f64>::new()
+        );
+    }
+}
@@ -0,0 +1,349 @@
+use document::{collapse_whitespace, CrawlResult, PageDocument};
+use encoding_rs::{UTF_16BE, WINDOWS_1252};
+use handler::ContentHandler;
+use link::Link;
+use lopdf::{Dictionary, Document, Object};
+use reqwest::header::Headers;
+use std::panic::{self, AssertUnwindSafe};
+use url::Url;
+
+/// How many pages have their text extracted.
+pub static DEFAULT_MAX_PAGES: usize = 500;
+/// How much text is kept from a whole document.
+pub static DEFAULT_MAX_TEXT_BYTES: usize = 2_000_000;
+/// How much of a PDF is downloaded. It's more than other files get, since one
+/// that's cut short can't be read at all.
+pub static DEFAULT_MAX_BYTES: u64 = 50_000_000;
+
+// what separates the text of each page, as in pdftotext
+static PAGE_BREAK: char = '\x0c';
+
+/// Handles PDFs: their text (page by page), title, author and subject, and
+/// the URLs of their link annotations.
+pub struct PdfHandler {
+    pub max_pages: usize,
+    pub max_text_bytes: usize,
+    pub max_bytes: u64,
+}
+
+impl Default for PdfHandler {
+    fn default() -> PdfHandler {
+        PdfHandler {
+            max_pages: DEFAULT_MAX_PAGES,
+            max_text_bytes: DEFAULT_MAX_TEXT_BYTES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+// follows a reference, if it is one
+fn resolve<'a>(document: &'a Document, object: &'a Object) -> &'a Object {
+    match *object {
+        Object::Reference(id) => document.get_object(id).unwrap_or(object),
+        _ => object,
+    }
+}
+
+fn get<'a>(document: &'a Document, dictionary: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dictionary.get(key).ok().map(|x| resolve(document, x))
+}
+
+/// Decodes a PDF text string, which is UTF-16 if it starts with a byte order
+/// mark and PDFDocEncoding (close enough to windows-1252) otherwise.
+pub fn text_string(bytes: &[u8]) -> String {
+    let text = if bytes.starts_with(b"\xfe\xff") {
+        UTF_16BE.decode_without_bom_handling(&bytes[2..]).0
+    } else {
+        WINDOWS_1252.decode_without_bom_handling(bytes).0
+    };
+    return collapse_whitespace(&text);
+}
+
+fn info_string(document: &Document, info: Option<&Dictionary>, key: &[u8]) -> String {
+    match info.and_then(|x| get(document, x, key)) {
+        Some(&Object::String(ref bytes, _)) => text_string(bytes),
+        _ => String::new(),
+    }
+}
+
+// the URIs of a page's link annotations
+fn annotation_uris(document: &Document, page: &Dictionary) -> Vec<String> {
+    let annotations = match get(document, page, b"Annots").map(|x| x.as_array()) {
+        Some(Ok(annotations)) => annotations,
+        _ => return Vec::new(),
+    };
+
+    let mut uris = Vec::new();
+    for annotation in annotations {
+        let action = resolve(document, annotation)
+            .as_dict()
+            .ok()
+            .and_then(|x| get(document, x, b"A"))
+            .and_then(|x| x.as_dict().ok());
+
+        if let Some(action) = action {
+            if let Some(&Object::String(ref uri, _)) = get(document, action, b"URI") {
+                uris.push(String::from_utf8_lossy(uri).trim().to_string());
+            }
+        }
+    }
+    return uris;
+}
+
+impl PdfHandler {
+    fn read(&self, url: &Url, body: &[u8]) -> Result<CrawlResult, String> {
+        let document = Document::load_mem(body).map_err(|x| format!("{:?}", x))?;
+        if document.trailer.get(b"Encrypt").is_ok() {
+            return Err("it's encrypted".to_string());
+        }
+
+        let mut result = CrawlResult::new("pdf");
+        let mut page_document = PageDocument::default();
+
+        let info = document
+            .trailer
+            .get(b"Info")
+            .ok()
+            .map(|x| resolve(&document, x))
+            .and_then(|x| x.as_dict().ok());
+        page_document.title = info_string(&document, info, b"Title");
+        page_document.description = info_string(&document, info, b"Subject");
+        let author = info_string(&document, info, b"Author");
+        if author.len() != 0 {
+            result.meta.push(("author".to_string(), author));
+        }
+
+        let pages = document.get_pages();
+        if pages.len() > self.max_pages {
+            info!(
+                "only reading {} of {}'s {} pages",
+                self.max_pages,
+                url,
+                pages.len()
+            );
+        }
+
+        let mut texts = Vec::new();
+        let mut length = 0;
+        for (number, id) in pages.into_iter().take(self.max_pages) {
+            if let Ok(page) = document.get_dictionary(id) {
+                for uri in annotation_uris(&document, page) {
+                    match url.join(&uri) {
+                        Ok(joined) => {
+                            let joined = joined.to_string();
+                            if !result.links.iter().any(|x| x.url == joined) {
+                                let mut link = Link::new(joined);
+                                link.element = "annot".to_string();
+                                link.attribute = "uri".to_string();
+                                result.links.push(link);
+                            }
+                        }
+                        Err(error) => debug!("bad URI {:?} in {}: {}", uri, url, error),
+                    }
+                }
+            }
+
+            if length >= self.max_text_bytes {
+                continue;
+            }
+            // one page that can't be read shouldn't lose the rest
+            let mut text = match document.extract_text(&[number]) {
+                Ok(text) => collapse_whitespace(&text),
+                Err(error) => {
+                    debug!("couldn't read page {} of {}: {:?}", number, url, error);
+                    String::new()
+                }
+            };
+            if length + text.len() > self.max_text_bytes {
+                let mut end = self.max_text_bytes - length;
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                text.truncate(end);
+            }
+            length += text.len() + 1;
+            texts.push(text);
+        }
+
+        page_document.text = texts.join(&PAGE_BREAK.to_string());
+        page_document.word_count = page_document.text.split_whitespace().count();
+        result.document = Some(page_document);
+        return Ok(result);
+    }
+}
+
+impl ContentHandler for PdfHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "application/pdf" || mime == "application/x-pdf"
+    }
+
+    fn max_bytes(&self) -> Option<u64> {
+        Some(self.max_bytes)
+    }
+
+    fn process(&self, url: &Url, _headers: &Headers, body: &[u8]) -> CrawlResult {
+        // lopdf can panic on broken files, which shouldn't take the crawl down with them
+        let read = panic::catch_unwind(AssertUnwindSafe(|| self.read(url, body)))
+            .unwrap_or_else(|_| Err("the parser panicked".to_string()));
+
+        match read {
+            Ok(result) => result,
+            Err(error) => {
+                warn!("couldn't read PDF {}: {}", url, error);
+                // an empty document, so the bytes aren't indexed as text
+                CrawlResult {
+                    document: Some(PageDocument::default()),
+                    index: false,
+                    ..CrawlResult::new("pdf")
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pdf::*;
+    use lopdf::content::{Content, Operation};
+    use lopdf::{Stream, StringFormat};
+
+    // a PDF with a line of text and a link on each page
+    fn build_pdf(pages: &[(&str, &str)]) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+
+        let mut kids = Vec::new();
+        for &(text, uri) in pages {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![100.into(), 600.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id =
+                document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            let annotation_id = document.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "A" => dictionary! {
+                    "S" => "URI",
+                    "URI" => Object::String(uri.as_bytes().to_vec(), StringFormat::Literal),
+                },
+            });
+            let page_id = document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+                "Annots" => vec![annotation_id.into()],
+            });
+            kids.push(page_id.into());
+        }
+
+        let count = kids.len() as i64;
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => dictionary! {
+                    "Font" => dictionary! { "F1" => font_id },
+                },
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = document.add_object(dictionary! {
+            "Title" => Object::string_literal("Annual  report"),
+            // UTF-16, as non-ASCII titles are
+            "Author" => Object::String(
+                b"\xfe\xff\x00J\x00o\x00s\x00\xe9".to_vec(),
+                StringFormat::Hexadecimal,
+            ),
+        });
+        document.trailer.set("Root", catalog_id);
+        document.trailer.set("Info", info_id);
+
+        let mut body = Vec::new();
+        document.save_to(&mut body).unwrap();
+        return body;
+    }
+
+    #[test]
+    fn _text_string() {
+        assert_eq!(text_string(b"Caf\xe9 \n menu"), "Café menu");
+        assert_eq!(text_string(b"\xfe\xff\x00h\x00i"), "hi");
+    }
+
+    #[test]
+    fn _pdf_handler() {
+        let url = Url::parse("https://google.com/docs/report.pdf").unwrap();
+        let body = build_pdf(&[
+            ("First page", "https://google.com/news"),
+            ("Second page", "summary.html"),
+        ]);
+
+        let result = PdfHandler::default().process(&url, &Headers::new(), &body);
+        assert_eq!(result.kind, "pdf");
+        assert!(result.index);
+        assert_eq!(
+            result
+                .links
+                .iter()
+                .map(|x| x.url.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "https://google.com/news",
+                "https://google.com/docs/summary.html"
+            ]
+        );
+        assert_eq!(result.links[0].element, "annot");
+        assert_eq!(
+            result.meta,
+            vec![("author".to_string(), "José".to_string())]
+        );
+
+        let document = result.document.unwrap();
+        assert_eq!(document.title, "Annual report");
+        let pages: Vec<&str> = document.text.split(PAGE_BREAK).collect();
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("First page"));
+        assert!(pages[1].contains("Second page"));
+
+        // only the first page is read, and only part of it
+        let handler = PdfHandler {
+            max_pages: 1,
+            max_text_bytes: 5,
+            ..PdfHandler::default()
+        };
+        let document = handler
+            .process(&url, &Headers::new(), &body)
+            .document
+            .unwrap();
+        assert_eq!(document.text.len(), 5);
+    }
+
+    #[test]
+    fn _broken_pdf() {
+        let url = Url::parse("https://google.com/report.pdf").unwrap();
+        let body = build_pdf(&[("First page", "https://google.com/news")]);
+
+        for body in &[&body[..body.len() / 2], b"%PDF-1.4\n%garbage"] {
+            let result = PdfHandler::default().process(&url, &Headers::new(), body);
+            assert_eq!(result.document, Some(PageDocument::default()));
+            assert!(!result.index);
+            assert_eq!(result.links, Vec::new());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// where `location` can be reached from in a script
+static LOCATION_OBJECTS: [&str; 5] = ["", "window.", "document.", "self.", "top."];
+
+fn skip_whitespace(text: &str) -> &str {
+    text.trim_start_matches(|x: char| x.is_ascii_whitespace())
+}
+
+/// Parses the `content` of a `<meta http-equiv="refresh">`, like `5; url=/new`,
+/// as in the HTML spec: the delay in seconds and the URL, if it has one (if it
+/// doesn't, the page reloads itself).
+pub fn parse_refresh(content: &str) -> Option<(u32, Option<String>)> {
+    let input = skip_whitespace(content);
+
+    let digits = input
+        .find(|x: char| !x.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits == 0 && !input.starts_with('.') {
+        return None;
+    }
+    // `.5` is a delay of 0, and one too big to fit is as good as never
+    let delay = if digits == 0 {
+        0
+    } else {
+        input[..digits].parse().unwrap_or(u32::max_value())
+    };
+
+    // fractions of a second are allowed, and ignored
+    let mut rest = input[digits..].trim_start_matches(|x: char| x == '.' || x.is_ascii_digit());
+    match rest.chars().next() {
+        Some(c) if c != ';' && c != ',' && !c.is_ascii_whitespace() => return None,
+        _ => {}
+    }
+    rest = skip_whitespace(rest);
+    if rest.starts_with(';') || rest.starts_with(',') {
+        rest = skip_whitespace(&rest[1..]);
+    }
+
+    // url= is optional, and what's left wherever it stops matching is the URL
+    let mut url = rest;
+    let mut matched = 0;
+    for expected in "url".chars() {
+        if url.chars().next().map(|x| x.to_ascii_lowercase()) != Some(expected) {
+            break;
+        }
+        url = &url[1..];
+        matched += 1;
+    }
+    if matched == 3 {
+        url = skip_whitespace(url);
+        if url.starts_with('=') {
+            url = skip_whitespace(&url[1..]);
+        }
+    }
+
+    let url = match url.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => {
+            let url = &url[1..];
+            &url[..url.find(quote).unwrap_or(url.len())]
+        }
+        _ => url,
+    };
+    let url = url.trim();
+    return Some((
+        delay,
+        if url.len() == 0 {
+            None
+        } else {
+            Some(url.to_string())
+        },
+    ));
+}
+
+// reads a string literal that's all of `text`, without any escapes
+fn string_literal(text: &str) -> Option<&str> {
+    let quote = text.chars().next()?;
+    if (quote != '"' && quote != '\'') || text.len() < 2 || !text.ends_with(quote) {
+        return None;
+    }
+
+    let value = &text[1..text.len() - 1];
+    if value.contains(quote) || value.contains('\\') {
+        return None;
+    }
+    return Some(value);
+}
+
+/// Finds where an inline script redirects to, but only if redirecting is
+/// all it does, like `window.location = "/new";` or
+/// `location.replace('/new')`. Anything more complicated is left alone.
+pub fn script_redirect(script: &str) -> Option<String> {
+    let mut statement = script.trim();
+    // an HTML comment around the script, for very old browsers
+    if statement.len() >= 7 && statement.starts_with("<!--") && statement.ends_with("-->") {
+        statement = statement[4..statement.len() - 3]
+            .trim()
+            .trim_end_matches("//")
+            .trim();
+    }
+    let statement = statement.trim_end_matches(';').trim();
+    if statement.contains(';') || statement.contains('\n') {
+        return None;
+    }
+
+    let position = statement.find("location")?;
+    if !LOCATION_OBJECTS.contains(&&statement[..position]) {
+        return None;
+    }
+    let rest = &statement[position + "location".len()..];
+
+    let value = if rest.starts_with(".replace(") || rest.starts_with(".assign(") {
+        let argument = &rest[rest.find('(').unwrap() + 1..];
+        if !argument.ends_with(')') {
+            return None;
+        }
+        argument[..argument.len() - 1].trim()
+    } else {
+        let rest = if rest.starts_with(".href") {
+            &rest[5..]
+        } else {
+            rest
+        };
+        let rest = skip_whitespace(rest);
+        if !rest.starts_with('=') || rest.starts_with("==") {
+            return None;
+        }
+        skip_whitespace(&rest[1..])
+    };
+
+    let url = string_literal(value)?.trim();
+    if url.len() == 0 || url.to_ascii_lowercase().starts_with("javascript:") {
+        return None;
+    }
+    return Some(url.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use redirect::*;
+
+    #[test]
+    fn _parse_refresh() {
+        let refresh = |delay: u32, url: &str| Some((delay, Some(url.to_string())));
+
+        assert_eq!(
+            parse_refresh("0; url=https://google.com/"),
+            refresh(0, "https://google.com/")
+        );
+        assert_eq!(parse_refresh(" 5 ,URL = '/news' "), refresh(5, "/news"));
+        assert_eq!(parse_refresh("3;\"/news\""), refresh(3, "/news"));
+        assert_eq!(parse_refresh("1.5; url=news"), refresh(1, "news"));
+        assert_eq!(parse_refresh(".5; url=news"), refresh(0, "news"));
+        // without url=, the rest is still the URL
+        assert_eq!(parse_refresh("0; news.html"), refresh(0, "news.html"));
+        assert_eq!(parse_refresh("0; urlnews.html"), refresh(0, "news.html"));
+        assert_eq!(parse_refresh("30"), Some((30, None)));
+        assert_eq!(parse_refresh("0; url="), Some((0, None)));
+        assert_eq!(parse_refresh("soon; url=/news"), None);
+        assert_eq!(parse_refresh("5s; url=/news"), None);
+        assert_eq!(parse_refresh(""), None);
+    }
+
+    #[test]
+    fn _script_redirect() {
+        assert_eq!(
+            script_redirect(" window.location = \"https://google.com/\";\n"),
+            Some("https://google.com/".to_string())
+        );
+        assert_eq!(
+            script_redirect("location.href='/news'"),
+            Some("/news".to_string())
+        );
+        assert_eq!(
+            script_redirect("<!--\ndocument.location = '/news';\n//-->"),
+            Some("/news".to_string())
+        );
+        assert_eq!(
+            script_redirect("window.location.replace( \"/news\" );"),
+            Some("/news".to_string())
+        );
+
+        for script in &[
+            "if (mobile) window.location = '/m';",
+            "window.location = '/a'; track();",
+            "window.location = base + '/news';",
+            "window.location = '/it\\'s';",
+            "window.location == '/news'",
+            "mylocation = '/news'",
+            "window.location = 'javascript:void(0)'",
+            "window.location.reload()",
+            "<!-->",
+            "<!--->",
+        ] {
+            assert_eq!(script_redirect(script), None, "{}", script);
+        }
+    }
+}
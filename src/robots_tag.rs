@@ -0,0 +1,175 @@
+use reqwest::header::Headers;
+
+// directives that carry a value after a colon, so "name: value" isn't a user-agent scope
+static VALUED_DIRECTIVES: [&str; 4] = [
+    "unavailable_after",
+    "max-snippet",
+    "max-image-preview",
+    "max-video-preview",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RobotsDirectives {
+    pub index: bool,
+    pub follow: bool,
+}
+
+impl Default for RobotsDirectives {
+    fn default() -> RobotsDirectives {
+        RobotsDirectives {
+            index: true,
+            follow: true,
+        }
+    }
+}
+
+impl RobotsDirectives {
+    /// Applies a comma-separated list of directives, such as the `content` of a robots `<meta>`.
+    pub fn apply(&mut self, directives: &str) {
+        for robots_command in directives.split(",").map(|x| x.trim().to_lowercase()) {
+            debug!("found robot-command {}", robots_command);
+
+            match robots_command.as_str() {
+                "nofollow" => {
+                    self.follow = false;
+                }
+                "noindex" => {
+                    self.index = false;
+                }
+                "none" => {
+                    self.index = false;
+                    self.follow = false;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies a single `X-Robots-Tag` value, which may be scoped to a user-agent
+    /// (`twentiethbot: noindex`). Values scoped to other user-agents are ignored.
+    pub fn apply_header_value(&mut self, value: &str, useragent: &str) {
+        let mut split = value.splitn(2, ':');
+        let scope = split.nth(0).unwrap().trim().to_lowercase();
+
+        // a user-agent is one word, so "noindex, unavailable_after: ..." isn't scoped
+        let is_useragent = !VALUED_DIRECTIVES.contains(&scope.as_str())
+            && !scope.contains(|x: char| x == ',' || x.is_whitespace());
+
+        match split.nth(0) {
+            Some(directives) if is_useragent => {
+                if scope == useragent.to_lowercase() {
+                    self.apply(directives);
+                } else {
+                    trace!("ignoring X-Robots-Tag for {}", scope);
+                }
+            }
+            _ => self.apply(value),
+        }
+    }
+}
+
+pub fn find_in_headers(headers: &Headers, useragent: &str) -> RobotsDirectives {
+    let mut directives = RobotsDirectives::default();
+
+    if let Some(raw) = headers.get_raw("X-Robots-Tag") {
+        for line in raw.iter() {
+            directives.apply_header_value(&String::from_utf8_lossy(line), useragent);
+        }
+    }
+
+    return directives;
+}
+
+#[cfg(test)]
+mod tests {
+    use robots_tag::*;
+
+    #[test]
+    fn _apply() {
+        let mut directives = RobotsDirectives::default();
+        directives.apply("noarchive, NOINDEX");
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                index: false,
+                follow: true,
+            }
+        );
+
+        let mut directives = RobotsDirectives::default();
+        directives.apply("none");
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                index: false,
+                follow: false,
+            }
+        );
+    }
+
+    #[test]
+    fn _apply_header_value() {
+        let mut directives = RobotsDirectives::default();
+        directives.apply_header_value("otherbot: noindex", "twentiethbot");
+        directives.apply_header_value(
+            "unavailable_after: 25 Jun 2010 15:00:00 PST",
+            "twentiethbot",
+        );
+        assert_eq!(directives, RobotsDirectives::default());
+
+        directives.apply_header_value("TwentiethBot: nofollow", "twentiethbot");
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                index: true,
+                follow: false,
+            }
+        );
+
+        let mut directives = RobotsDirectives::default();
+        directives.apply_header_value(
+            "noindex, unavailable_after: 25 Jun 2010 15:00:00 PST",
+            "twentiethbot",
+        );
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                index: false,
+                follow: true,
+            }
+        );
+
+        let mut directives = RobotsDirectives::default();
+        directives.apply_header_value(
+            "twentiethbot: nofollow, unavailable_after: 25 Jun 2010",
+            "twentiethbot",
+        );
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                index: true,
+                follow: false,
+            }
+        );
+    }
+
+    #[test]
+    fn _find_in_headers() {
+        let mut headers = Headers::new();
+        assert_eq!(
+            find_in_headers(&headers, "twentiethbot"),
+            RobotsDirectives::default()
+        );
+
+        headers.append_raw("X-Robots-Tag", "noindex");
+        headers.append_raw("X-Robots-Tag", "twentiethbot: nofollow");
+        headers.append_raw("X-Robots-Tag", "otherbot: none");
+        assert_eq!(
+            find_in_headers(&headers, "twentiethbot"),
+            RobotsDirectives {
+                index: false,
+                follow: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use chrono::Utc;
+use flate2;
+use flate2::write::GzEncoder;
+use options::{Compression, Rotation};
+use sink::Finish;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+use zstd;
+
+enum Encoder {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<BufWriter<File>>),
+}
+
+impl Encoder {
+    fn new(file: File, compression: Compression) -> io::Result<Encoder> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Encoder::Plain(file),
+            Compression::Gzip => {
+                Encoder::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match *self {
+            Encoder::Plain(ref mut file) => file,
+            Encoder::Gzip(ref mut encoder) => encoder,
+            Encoder::Zstd(ref mut encoder) => encoder,
+        }
+    }
+
+    // writes out whatever's left and makes sure it's on disk
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Encoder::Plain(file) => file,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        return file.get_ref().sync_all();
+    }
+}
+
+// the part that's being written to
+struct Part {
+    path: String,
+    encoder: Encoder,
+    bytes: u64,
+    records: u64,
+    started: Instant,
+}
+
+/// Writes an output to a series of parts, optionally compressed. A part is
+/// written to `NAME.tmp` and only renamed to `NAME` once it's finished, at which
+/// point it's also added to `PATH.manifest` as `name \t records \t bytes \t time`,
+/// so other jobs can pick up finished parts while the crawl goes on.
+///
+/// Records are lines, and parts are only rotated when the writer is flushed, so
+/// a part never ends in the middle of one.
+pub struct RotatingFile {
+    path: String,
+    rotation: Rotation,
+    compression: Compression,
+    part: Option<Part>,
+    serial: u32,
+}
+
+impl RotatingFile {
+    pub fn new(path: String, rotation: Rotation, compression: Compression) -> RotatingFile {
+        RotatingFile {
+            path: path,
+            rotation: rotation,
+            compression: compression,
+            part: None,
+            serial: 0,
+        }
+    }
+
+    /// Where the `serial`th part goes: `pages.jsonl` is split into
+    /// `pages-00000.jsonl.gz`, `pages-00001.jsonl.gz`...
+    fn part_path(&self, serial: u32) -> String {
+        let extension = match self.compression {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        };
+        if !self.rotation.is_enabled() {
+            return format!("{}{}", self.path, extension);
+        }
+
+        let filename = self.path.rfind('/').map(|x| x + 1).unwrap_or(0);
+        let dot = match self.path[filename..].rfind('.') {
+            Some(dot) if dot > 0 => filename + dot,
+            _ => self.path.len(),
+        };
+
+        return format!(
+            "{}-{:05}{}{}",
+            &self.path[..dot],
+            serial,
+            &self.path[dot..],
+            extension
+        );
+    }
+
+    fn manifest_path(&self) -> String {
+        format!("{}.manifest", self.path)
+    }
+
+    fn start_part(&mut self) -> io::Result<()> {
+        let path = self.part_path(self.serial);
+        self.serial += 1;
+        debug!("writing to {}.tmp", path);
+
+        let file = File::create(format!("{}.tmp", path))?;
+        self.part = Some(Part {
+            encoder: Encoder::new(file, self.compression)?,
+            path: path,
+            bytes: 0,
+            records: 0,
+            started: Instant::now(),
+        });
+        return Ok(());
+    }
+
+    fn is_due(&self) -> bool {
+        let part = match self.part {
+            Some(ref part) => part,
+            None => return false,
+        };
+        let rotation = &self.rotation;
+
+        (rotation.max_bytes > 0 && part.bytes >= rotation.max_bytes)
+            || (rotation.max_records > 0 && part.records >= rotation.max_records)
+            || (rotation.max_seconds > 0
+                && part.started.elapsed() >= Duration::from_secs(rotation.max_seconds))
+    }
+
+    /// Finishes the current part, if anything's been written to it.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let part = match self.part.take() {
+            Some(part) => part,
+            None => return Ok(()),
+        };
+
+        part.encoder.finish()?;
+        fs::rename(format!("{}.tmp", part.path), &part.path)?;
+        info!("finished {} ({} records)", part.path, part.records);
+
+        let name = part.path.rsplit('/').next().unwrap();
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.manifest_path())?;
+        return writeln!(
+            manifest,
+            "{}\t{}\t{}\t{}",
+            name,
+            part.records,
+            fs::metadata(&part.path)?.len(),
+            Utc::now().to_rfc3339()
+        );
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.part.is_none() {
+            self.start_part()?;
+        }
+
+        let part = self.part.as_mut().unwrap();
+        let written = part.encoder.writer().write(buf)?;
+        part.bytes += written as u64;
+        part.records += buf[..written].iter().filter(|x| **x == b'\n').count() as u64;
+        return Ok(written);
+    }
+
+    // parts aren't readable until they're finished anyway, so this only rotates
+    fn flush(&mut self) -> io::Result<()> {
+        if self.is_due() {
+            return self.finish();
+        }
+        return Ok(());
+    }
+}
+
+impl Finish for RotatingFile {
+    fn finish(&mut self) -> io::Result<()> {
+        RotatingFile::finish(self)
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            warn!("error finishing {}: {}", self.path, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rotate::*;
+    use flate2::read::GzDecoder;
+    use link::Link;
+    use link_graph::LinkGraph;
+    use options::Format;
+    use sink::OutputSink;
+    use std::io::Read;
+    use uuid::Uuid;
+
+    #[test]
+    fn _part_path() {
+        let mut rotation = Rotation::default();
+        let file = RotatingFile::new("out/pages.jsonl".to_string(), rotation, Compression::Gzip);
+        assert_eq!(file.part_path(0), "out/pages.jsonl.gz");
+
+        rotation.max_records = 10;
+        let file = RotatingFile::new("out/pages.jsonl".to_string(), rotation, Compression::Zstd);
+        assert_eq!(file.part_path(3), "out/pages-00003.jsonl.zst");
+        let file = RotatingFile::new("out.d/links".to_string(), rotation, Compression::None);
+        assert_eq!(file.part_path(0), "out.d/links-00000");
+        let file = RotatingFile::new(".links".to_string(), rotation, Compression::None);
+        assert_eq!(file.part_path(0), ".links-00000");
+    }
+
+    #[test]
+    fn _rotating_file() {
+        let directory = ::std::env::temp_dir().join(format!("crawler-rotate-{}", Uuid::new_v4()));
+        fs::create_dir(&directory).unwrap();
+        let path = directory.join("pages.tsv").to_str().unwrap().to_string();
+
+        let rotation = Rotation {
+            max_records: 2,
+            ..Rotation::default()
+        };
+        let mut file = RotatingFile::new(path.clone(), rotation, Compression::Gzip);
+        for line in &["a\n", "b\n", "c\n"] {
+            // split up like a BufWriter might
+            file.write_all(&line.as_bytes()[..1]).unwrap();
+            file.write_all(&line.as_bytes()[1..]).unwrap();
+            file.flush().unwrap();
+        }
+
+        // the second part isn't finished yet
+        let first = directory.join("pages-00000.tsv.gz");
+        let second = directory.join("pages-00001.tsv.gz");
+        assert!(first.exists());
+        assert!(!second.exists());
+        assert!(directory.join("pages-00001.tsv.gz.tmp").exists());
+
+        drop(file);
+        assert!(second.exists());
+        assert!(!directory.join("pages-00001.tsv.gz.tmp").exists());
+
+        let mut contents = String::new();
+        GzDecoder::new(File::open(&first).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "a\nb\n");
+
+        let manifest = fs::read_to_string(format!("{}.manifest", path)).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let lines: Vec<Vec<&str>> = manifest.lines().map(|x| x.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(&lines[0][..2], &["pages-00000.tsv.gz", "2"]);
+        assert_eq!(&lines[1][..2], &["pages-00001.tsv.gz", "1"]);
+    }
+
+    #[test]
+    fn _close() {
+        let directory = ::std::env::temp_dir().join(format!("crawler-rotate-{}", Uuid::new_v4()));
+        fs::create_dir(&directory).unwrap();
+        let path = directory.join("links.tsv").to_str().unwrap().to_string();
+        let time = Utc::now();
+        let mut link = Link::new("https://google.com/news".to_string());
+        link.element = "a".to_string();
+
+        let rotation = Rotation {
+            max_records: 10,
+            ..Rotation::default()
+        };
+        let file = RotatingFile::new(path, rotation, Compression::None);
+        let mut graph = LinkGraph::new(file, Format::Tsv);
+        graph.on_link("https://google.com/", &link, time).unwrap();
+        // closing finishes the part, without waiting for the writer to be dropped
+        graph.close().unwrap();
+        assert!(directory.join("links-00000.tsv").exists());
+
+        // and says so if it couldn't
+        graph.on_link("https://google.com/", &link, time).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(graph.close().is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use chrono::{DateTime, Utc};
+use feed::FeedEntry;
+use link::Link;
+use options::PageFilter;
+use output::PageRecord;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// A request that was sent, whether or not it succeeded.
+pub struct Fetch<'a> {
+    pub url: &'a str,
+    pub method: &'a str,
+    pub attempt: u32,
+    /// The response's status, if there was one.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration: Duration,
+    pub time: DateTime<Utc>,
+}
+
+/// Somewhere crawl results are written to.
+pub trait OutputSink {
+    /// Called for every indexable page that passes the sink's filter.
+    fn on_page(&mut self, page: &PageRecord, body: &[u8]) -> io::Result<()>;
+
+    /// Called for every link found, whether or not it's followed.
+    fn on_link(&mut self, _source: &str, _link: &Link, _time: DateTime<Utc>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for every item of every feed.
+    fn on_entry(&mut self, _entry: &FeedEntry) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for every request that got a response.
+    fn on_fetch(&mut self, _fetch: &Fetch) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for every request that failed.
+    fn on_error(&mut self, _fetch: &Fetch) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called after every page, so whatever's buffered can be written out.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the crawl is over.
+    fn close(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// A writer that has to be told nothing more is coming, like a rotating file
+/// whose last part is only finished then.
+pub trait Finish: Write {
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Finish for Vec<u8> {}
+impl Finish for io::Stdout {}
+impl<W: Write> Finish for io::BufWriter<W> {}
+
+impl<F: Finish + ?Sized> Finish for Box<F> {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<'a, F: Finish + ?Sized> Finish for &'a mut F {
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+struct Entry {
+    name: String,
+    sink: Box<dyn OutputSink>,
+    filter: PageFilter,
+}
+
+/// Sends everything to several sinks. A sink that fails is logged and
+/// skipped rather than stopping the others.
+#[derive(Default)]
+pub struct Sinks {
+    entries: Vec<Entry>,
+}
+
+impl Sinks {
+    pub fn new() -> Sinks {
+        Sinks::default()
+    }
+
+    pub fn add(&mut self, name: String, sink: Box<dyn OutputSink>, filter: PageFilter) {
+        self.entries.push(Entry {
+            name: name,
+            sink: sink,
+            filter: filter,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn each<F: FnMut(&mut Entry) -> Option<io::Result<()>>>(&mut self, mut call: F) {
+        for entry in &mut self.entries {
+            if let Some(Err(error)) = call(entry) {
+                warn!("error writing to {}: {}", entry.name, error);
+            }
+        }
+    }
+
+    pub fn on_page(&mut self, page: &PageRecord, body: &[u8]) {
+        self.each(|entry| {
+            if !entry.filter.matches(page.status, &page.sniffed_type) {
+                return None;
+            }
+            Some(entry.sink.on_page(page, body))
+        });
+    }
+
+    pub fn on_link(&mut self, source: &str, link: &Link, time: DateTime<Utc>) {
+        self.each(|entry| Some(entry.sink.on_link(source, link, time)));
+    }
+
+    pub fn on_entry(&mut self, feed_entry: &FeedEntry) {
+        self.each(|entry| Some(entry.sink.on_entry(feed_entry)));
+    }
+
+    pub fn on_fetch(&mut self, fetch: &Fetch) {
+        self.each(|entry| Some(entry.sink.on_fetch(fetch)));
+    }
+
+    pub fn on_error(&mut self, fetch: &Fetch) {
+        self.each(|entry| Some(entry.sink.on_error(fetch)));
+    }
+
+    pub fn flush(&mut self) {
+        self.each(|entry| Some(entry.sink.flush()));
+    }
+
+    pub fn close(&mut self) {
+        self.each(|entry| Some(entry.sink.close()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sink::*;
+    use document::PageDocument;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    // writes down what it was called with
+    struct TestSink(Rc<RefCell<Vec<String>>>);
+
+    impl OutputSink for TestSink {
+        fn on_page(&mut self, page: &PageRecord, _body: &[u8]) -> io::Result<()> {
+            self.0.borrow_mut().push(format!("page {}", page.url));
+            if page.status == 500 {
+                return Err(io::Error::new(io::ErrorKind::Other, "oops"));
+            }
+            Ok(())
+        }
+
+        fn on_link(&mut self, _source: &str, link: &Link, _time: DateTime<Utc>) -> io::Result<()> {
+            self.0.borrow_mut().push(format!("link {}", link.url));
+            Ok(())
+        }
+
+        fn close(&mut self) -> io::Result<()> {
+            self.0.borrow_mut().push("close".to_string());
+            Ok(())
+        }
+    }
+
+    fn page(url: &str, status: u16, content_type: &str) -> PageRecord {
+        PageRecord {
+            url: url.to_string(),
+            final_url: url.to_string(),
+            redirect: None,
+            status: status,
+            content_type: content_type.to_string(),
+            sniffed_type: content_type.to_string(),
+            encoding: "UTF-8".to_string(),
+            truncated: String::new(),
+            depth: 0,
+            fetch_time: String::new(),
+            headers: BTreeMap::new(),
+            document: PageDocument::default(),
+            meta: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn _sinks() {
+        let all = Rc::new(RefCell::new(Vec::new()));
+        let html = Rc::new(RefCell::new(Vec::new()));
+
+        let mut sinks = Sinks::new();
+        sinks.add(
+            "all".to_string(),
+            Box::new(TestSink(all.clone())),
+            PageFilter::default(),
+        );
+        sinks.add(
+            "html".to_string(),
+            Box::new(TestSink(html.clone())),
+            PageFilter {
+                statuses: vec![200],
+                content_types: vec!["text/html".to_string()],
+            },
+        );
+        assert_eq!(sinks.len(), 2);
+
+        sinks.on_page(
+            &page("https://a.com/", 200, "text/html; charset=utf-8"),
+            b"",
+        );
+        sinks.on_page(&page("https://a.com/x.pdf", 200, "application/pdf"), b"");
+        sinks.on_page(&page("https://a.com/error", 500, "text/html"), b"");
+        sinks.on_link(
+            "https://a.com/",
+            &Link::new("https://b.com/".to_string()),
+            Utc::now(),
+        );
+        sinks.close();
+
+        assert_eq!(
+            *all.borrow(),
+            vec![
+                "page https://a.com/",
+                "page https://a.com/x.pdf",
+                "page https://a.com/error",
+                "link https://b.com/",
+                "close",
+            ]
+        );
+        assert_eq!(
+            *html.borrow(),
+            vec!["page https://a.com/", "link https://b.com/", "close"]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use document::CrawlResult;
+use flate2::read::GzDecoder;
+use handler::ContentHandler;
+use link::Link;
+use reqwest::header::Headers;
+use std::io::Read;
+use url::Url;
+use xml::reader::{EventReader, XmlEvent};
+
+/// sitemaps.org limits uncompressed sitemaps to 50MiB.
+pub static MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapEntry {
+    pub url: String,
+    pub lastmod: Option<String>,
+    pub changefreq: Option<String>,
+    pub priority: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sitemap {
+    /// A `<urlset>` or a text sitemap.
+    UrlSet(Vec<SitemapEntry>),
+    /// A `<sitemapindex>`, listing the URLs of more sitemaps.
+    Index(Vec<String>),
+}
+
+impl SitemapEntry {
+    pub fn new(url: String) -> SitemapEntry {
+        SitemapEntry {
+            url: url,
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+        }
+    }
+
+    /// Turns `<priority>` (0.0-1.0, default 0.5) into a frontier priority, nudged up
+    /// for recently modified or frequently changing pages and down for stale ones.
+    pub fn frontier_priority(&self, now: DateTime<Utc>) -> u32 {
+        let mut priority = (self.priority.unwrap_or(0.5).max(0.0).min(1.0) * 1000.0) as i64;
+
+        if let Some(lastmod) = self.lastmod.as_ref().and_then(|x| parse_lastmod(x)) {
+            priority += match now.signed_duration_since(lastmod).num_days() {
+                days if days <= 1 => 100,
+                days if days <= 7 => 50,
+                days if days <= 30 => 25,
+                days if days > 365 => -50,
+                _ => 0,
+            };
+        }
+
+        priority += match self.changefreq.as_ref().map(|x| x.as_str()) {
+            Some("always") | Some("hourly") => 50,
+            Some("daily") => 25,
+            Some("monthly") => -10,
+            Some("yearly") => -25,
+            Some("never") => -50,
+            _ => 0,
+        };
+
+        return priority.max(1).min(1000) as u32;
+    }
+}
+
+/// Parses a W3C datetime, which sitemaps allow to be anything from `2018` to a full timestamp.
+pub fn parse_lastmod(lastmod: &str) -> Option<DateTime<Utc>> {
+    let lastmod = lastmod.trim();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(lastmod) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    let mut date = lastmod.to_string();
+    match lastmod.len() {
+        4 => date.push_str("-01-01"),
+        7 => date.push_str("-01"),
+        _ => {}
+    }
+
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .ok()
+        .and_then(|x| x.and_hms_opt(0, 0, 0))
+        .map(|x| Utc.from_utc_datetime(&x))
+}
+
+/// Finds the `Sitemap:` lines of a robots.txt.
+pub fn find_sitemaps_in_robots(robotstxt: &str) -> Vec<String> {
+    let mut sitemaps = Vec::new();
+
+    for line in robotstxt.lines() {
+        let mut split = line.splitn(2, ':');
+        let field = split.nth(0).unwrap().trim().to_lowercase();
+
+        if field == "sitemap" {
+            if let Some(url) = split.nth(0) {
+                let url = url.split('#').nth(0).unwrap().trim();
+                if url.len() != 0 {
+                    sitemaps.push(url.to_string());
+                }
+            }
+        }
+    }
+
+    return sitemaps;
+}
+
+// sitemaps have to list absolute http(s) URLs, anything else can't be crawled
+fn crawlable_url(loc: &str) -> Option<String> {
+    match Url::parse(loc.trim()) {
+        Ok(ref url) if (url.scheme() == "http" || url.scheme() == "https") && url.has_host() => {
+            Some(url.to_string())
+        }
+        _ => {
+            debug!("ignoring {:?} in sitemap", loc);
+            None
+        }
+    }
+}
+
+/// Parses a (possibly gzipped) XML or text sitemap.
+pub fn parse_sitemap(body: &[u8]) -> Option<Sitemap> {
+    let mut decompressed = Vec::new();
+
+    if body.starts_with(&[0x1f, 0x8b]) {
+        let result = GzDecoder::new(body)
+            .take(MAX_SITEMAP_SIZE)
+            .read_to_end(&mut decompressed);
+
+        if result.is_err() {
+            warn!("error decompressing sitemap: {:?}", result);
+            return None;
+        }
+    } else {
+        decompressed.extend_from_slice(body);
+    }
+
+    let text = String::from_utf8_lossy(&decompressed);
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+
+    if trimmed.starts_with("<") {
+        return parse_xml_sitemap(trimmed);
+    }
+
+    return Some(Sitemap::UrlSet(
+        trimmed
+            .lines()
+            .filter(|x| x.trim().len() != 0)
+            .filter_map(crawlable_url)
+            .map(SitemapEntry::new)
+            .collect(),
+    ));
+}
+
+fn parse_xml_sitemap(xml: &str) -> Option<Sitemap> {
+    let mut is_index = None;
+    let mut entries = Vec::new();
+    let mut entry = SitemapEntry::new(String::new());
+    let mut element = String::new();
+
+    for event in EventReader::new(xml.as_bytes()) {
+        match event {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                if is_index == None {
+                    match name.local_name.as_str() {
+                        "urlset" => is_index = Some(false),
+                        "sitemapindex" => is_index = Some(true),
+                        _ => {
+                            debug!("not a sitemap (root element {})", name.local_name);
+                            return None;
+                        }
+                    }
+                }
+
+                element = name.local_name;
+            }
+            Ok(XmlEvent::Characters(text)) | Ok(XmlEvent::CData(text)) => {
+                let text = text.trim().to_string();
+                match element.as_str() {
+                    "loc" => entry.url.push_str(&text),
+                    "lastmod" => entry.lastmod = Some(text),
+                    "changefreq" => entry.changefreq = Some(text.to_lowercase()),
+                    "priority" => entry.priority = text.parse().ok(),
+                    _ => {}
+                }
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                if name.local_name == "url" || name.local_name == "sitemap" {
+                    if let Some(url) = crawlable_url(&entry.url) {
+                        entry.url = url;
+                        entries.push(entry);
+                    }
+                    entry = SitemapEntry::new(String::new());
+                }
+
+                element = String::new();
+            }
+            Err(error) => {
+                // keep whatever was found before the error, sitemaps are often truncated
+                warn!("error parsing sitemap: {}", error);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    match is_index {
+        Some(true) => Some(Sitemap::Index(entries.into_iter().map(|x| x.url).collect())),
+        Some(false) => Some(Sitemap::UrlSet(entries)),
+        None => None,
+    }
+}
+
+/// Handles XML sitemaps that turn up while crawling, such as ones linked from
+/// a page, by following everything they list.
+pub struct SitemapHandler;
+
+impl ContentHandler for SitemapHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "application/xml" || mime == "text/xml"
+    }
+
+    fn process(&self, _url: &Url, _headers: &Headers, body: &[u8]) -> CrawlResult {
+        let (element, urls) = match parse_sitemap(body) {
+            Some(Sitemap::UrlSet(entries)) => ("url", entries.into_iter().map(|x| x.url).collect()),
+            Some(Sitemap::Index(sitemaps)) => ("sitemap", sitemaps),
+            None => ("", Vec::new()),
+        };
+
+        let mut result = CrawlResult::new("sitemap");
+        // there's nothing to index in a list of URLs
+        result.index = false;
+        for url in urls {
+            let mut link = Link::new(url);
+            link.element = element.to_string();
+            link.attribute = "loc".to_string();
+            result.links.push(link);
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sitemap::*;
+    use std::io::Write;
+
+    #[test]
+    fn _find_sitemaps_in_robots() {
+        assert_eq!(
+            find_sitemaps_in_robots(
+                "User-agent: *\nDisallow: /private\nSitemap: https://google.com/a.xml\n\
+                 sitemap:https://google.com/b.xml # comment\nSitemap:\n"
+            ),
+            vec![
+                "https://google.com/a.xml".to_string(),
+                "https://google.com/b.xml".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn _parse_sitemap() {
+        let urlset = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+            <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">
+              <url>
+                <loc>https://google.com/news</loc>
+                <lastmod>2018-05-01</lastmod>
+                <changefreq>Daily</changefreq>
+                <priority>0.8</priority>
+              </url>
+              <url><loc>https://google.com/gmail</loc></url>
+              <url><loc>/news</loc><priority>1.0</priority></url>
+              <url><loc>http://bad host/</loc></url>
+              <url><loc>urn:x</loc></url>
+            </urlset>";
+        let expected = Some(Sitemap::UrlSet(vec![
+            SitemapEntry {
+                url: "https://google.com/news".to_string(),
+                lastmod: Some("2018-05-01".to_string()),
+                changefreq: Some("daily".to_string()),
+                priority: Some(0.8),
+            },
+            SitemapEntry::new("https://google.com/gmail".to_string()),
+        ]));
+        assert_eq!(parse_sitemap(urlset.as_bytes()), expected);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(urlset.as_bytes()).unwrap();
+        assert_eq!(parse_sitemap(&encoder.finish().unwrap()), expected);
+
+        assert_eq!(
+            parse_sitemap(
+                b"<sitemapindex><sitemap><loc>https://google.com/a.xml</loc></sitemap>\
+                  <sitemap><loc><![CDATA[https://google.com/b.xml]]></loc></sitemap></sitemapindex>"
+            ),
+            Some(Sitemap::Index(vec![
+                "https://google.com/a.xml".to_string(),
+                "https://google.com/b.xml".to_string(),
+            ]))
+        );
+
+        assert_eq!(
+            parse_sitemap(
+                b"https://google.com/news\n\nnot a url\r\nhttps://google.com/gmail\r\n\
+                  http://bad host/\nmailto:a@google.com\n"
+            ),
+            Some(Sitemap::UrlSet(vec![
+                SitemapEntry::new("https://google.com/news".to_string()),
+                SitemapEntry::new("https://google.com/gmail".to_string()),
+            ]))
+        );
+
+        assert_eq!(parse_sitemap(b"<html><body></body></html>"), None);
+    }
+
+    #[test]
+    fn _frontier_priority() {
+        let now = parse_lastmod("2018-05-10T12:00:00+00:00").unwrap();
+        let mut entry = SitemapEntry::new("https://google.com/".to_string());
+        assert_eq!(entry.frontier_priority(now), 500);
+
+        entry.priority = Some(0.8);
+        entry.lastmod = Some("2018-05-10".to_string());
+        entry.changefreq = Some("hourly".to_string());
+        assert_eq!(entry.frontier_priority(now), 950);
+
+        entry.priority = Some(0.1);
+        entry.lastmod = Some("2015".to_string());
+        entry.changefreq = Some("never".to_string());
+        assert_eq!(entry.frontier_priority(now), 1);
+    }
+}
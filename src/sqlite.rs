@@ -0,0 +1,415 @@
+use chrono::{DateTime, Utc};
+use feed::FeedEntry;
+use link::Link;
+use output::PageRecord;
+use rusqlite::{self, Connection};
+use sink::{Fetch, OutputSink};
+use std::io;
+use warc::digest;
+
+/// How many rows are written in each transaction.
+pub static DEFAULT_BATCH_SIZE: usize = 1000;
+
+static SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS pages (
+        id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL,
+        final_url TEXT NOT NULL,
+        redirect TEXT,
+        redirect_delay INTEGER,
+        status INTEGER NOT NULL,
+        content_type TEXT NOT NULL,
+        sniffed_type TEXT NOT NULL,
+        encoding TEXT NOT NULL,
+        truncated TEXT NOT NULL,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL,
+        canonical TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        text TEXT NOT NULL,
+        word_count INTEGER NOT NULL,
+        depth INTEGER NOT NULL,
+        body_digest TEXT NOT NULL,
+        text_digest TEXT NOT NULL,
+        fetched_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS pages_url ON pages (url);
+    CREATE INDEX IF NOT EXISTS pages_body_digest ON pages (body_digest);
+
+    CREATE TABLE IF NOT EXISTS links (
+        id INTEGER PRIMARY KEY,
+        source TEXT NOT NULL,
+        target TEXT NOT NULL,
+        anchor TEXT NOT NULL,
+        rel TEXT NOT NULL,
+        element TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        found_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS links_source ON links (source);
+    CREATE INDEX IF NOT EXISTS links_target ON links (target);
+
+    CREATE TABLE IF NOT EXISTS fetches (
+        id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL,
+        method TEXT NOT NULL,
+        attempt INTEGER NOT NULL,
+        status INTEGER,
+        error TEXT,
+        duration_ms INTEGER NOT NULL,
+        fetched_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS fetches_url ON fetches (url);
+
+    CREATE TABLE IF NOT EXISTS feed_entries (
+        id INTEGER PRIMARY KEY,
+        feed TEXT NOT NULL,
+        url TEXT NOT NULL,
+        title TEXT NOT NULL,
+        published TEXT NOT NULL,
+        author TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS feed_entries_url ON feed_entries (url);
+";
+
+/// Stores pages, links and the fetch log in an SQLite database, committing
+/// every `batch_size` rows instead of after each one.
+pub struct SqliteSink {
+    connection: Connection,
+    batch_size: usize,
+    pending: usize,
+}
+
+impl SqliteSink {
+    pub fn open(path: &str, batch_size: usize) -> rusqlite::Result<SqliteSink> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(SqliteSink {
+            connection: connection,
+            batch_size: batch_size,
+            pending: 0,
+        })
+    }
+
+    fn begin(&mut self) -> rusqlite::Result<()> {
+        if self.connection.is_autocommit() {
+            self.connection.execute_batch("BEGIN")?;
+        }
+        return Ok(());
+    }
+
+    fn wrote(&mut self, rows: usize) -> rusqlite::Result<()> {
+        self.pending += rows;
+        if self.pending >= self.batch_size {
+            return self.commit();
+        }
+        return Ok(());
+    }
+
+    pub fn write_page(&mut self, record: &PageRecord, body: &[u8]) -> rusqlite::Result<()> {
+        self.begin()?;
+
+        let document = &record.document;
+        self.connection.execute(
+            "INSERT INTO pages (url, final_url, redirect, redirect_delay, status, content_type,
+                sniffed_type, encoding, truncated, title, description, canonical, lang, text,
+                word_count, depth, body_digest, text_digest, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                ?17, ?18, ?19)",
+            params![
+                record.url,
+                record.final_url,
+                record.redirect.as_ref().map(|x| x.url.clone()),
+                record.redirect.as_ref().map(|x| x.delay),
+                record.status,
+                record.content_type,
+                record.sniffed_type,
+                record.encoding,
+                record.truncated,
+                document.title,
+                document.description,
+                document.canonical,
+                document.lang,
+                document.text,
+                document.word_count as i64,
+                record.depth,
+                digest(body),
+                digest(document.text.as_bytes()),
+                record.fetch_time,
+            ],
+        )?;
+
+        return self.wrote(1);
+    }
+
+    pub fn write_link(
+        &mut self,
+        source: &str,
+        link: &Link,
+        time: DateTime<Utc>,
+    ) -> rusqlite::Result<()> {
+        // guessed root domains aren't actually linked to
+        if link.element.len() == 0 {
+            return Ok(());
+        }
+
+        self.begin()?;
+        self.connection
+            .prepare_cached(
+                "INSERT INTO links (source, target, anchor, rel, element, kind, found_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?
+            .execute(params![
+                source,
+                link.url,
+                link.anchor_text,
+                link.rel.join(" "),
+                link.element,
+                link.kind().as_str(),
+                time.to_rfc3339(),
+            ])?;
+
+        return self.wrote(1);
+    }
+
+    pub fn write_fetch(&mut self, fetch: &Fetch) -> rusqlite::Result<()> {
+        self.begin()?;
+
+        let duration = fetch.duration.as_secs() * 1000 + fetch.duration.subsec_millis() as u64;
+        self.connection.execute(
+            "INSERT INTO fetches (url, method, attempt, status, error, duration_ms, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                fetch.url,
+                fetch.method,
+                fetch.attempt,
+                fetch.status,
+                fetch.error,
+                duration as i64,
+                fetch.time.to_rfc3339(),
+            ],
+        )?;
+
+        return self.wrote(1);
+    }
+
+    pub fn write_entry(&mut self, entry: &FeedEntry) -> rusqlite::Result<()> {
+        self.begin()?;
+        self.connection
+            .prepare_cached(
+                "INSERT INTO feed_entries (feed, url, title, published, author)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                entry.feed,
+                entry.url,
+                entry.title,
+                entry.published,
+                entry.author,
+            ])?;
+
+        return self.wrote(1);
+    }
+
+    /// Commits everything written so far.
+    pub fn commit(&mut self) -> rusqlite::Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        return Ok(());
+    }
+}
+
+fn io_error(error: rusqlite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error)
+}
+
+// rows are committed in batches as they're written, so only closing commits early
+impl OutputSink for SqliteSink {
+    fn on_page(&mut self, page: &PageRecord, body: &[u8]) -> io::Result<()> {
+        self.write_page(page, body).map_err(io_error)
+    }
+
+    fn on_link(&mut self, source: &str, link: &Link, time: DateTime<Utc>) -> io::Result<()> {
+        self.write_link(source, link, time).map_err(io_error)
+    }
+
+    fn on_entry(&mut self, entry: &FeedEntry) -> io::Result<()> {
+        self.write_entry(entry).map_err(io_error)
+    }
+
+    fn on_fetch(&mut self, fetch: &Fetch) -> io::Result<()> {
+        self.write_fetch(fetch).map_err(io_error)
+    }
+
+    fn on_error(&mut self, fetch: &Fetch) -> io::Result<()> {
+        self.write_fetch(fetch).map_err(io_error)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.commit().map_err(io_error)
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        if let Err(error) = self.commit() {
+            warn!("error committing to SQLite: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlite::*;
+    use document::{PageDocument, PageRedirect};
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[test]
+    fn _sqlite_sink() {
+        let time = "2018-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut sink = SqliteSink::open(":memory:", 2).unwrap();
+
+        sink.write_page(
+            &PageRecord {
+                url: "https://google.com".to_string(),
+                final_url: "https://google.com/".to_string(),
+                redirect: Some(PageRedirect {
+                    url: "https://google.com/news".to_string(),
+                    delay: 0,
+                }),
+                status: 200,
+                content_type: "text/html".to_string(),
+                sniffed_type: "text/html".to_string(),
+                encoding: "UTF-8".to_string(),
+                truncated: String::new(),
+                depth: 0,
+                fetch_time: time.to_rfc3339(),
+                headers: BTreeMap::new(),
+                document: PageDocument {
+                    title: "Google".to_string(),
+                    text: "abc".to_string(),
+                    word_count: 1,
+                    ..PageDocument::default()
+                },
+                meta: Vec::new(),
+            },
+            b"<p>abc",
+        )
+        .unwrap();
+        // one row isn't a batch yet
+        assert_eq!(sink.pending, 1);
+
+        // a guessed root domain isn't written
+        sink.on_link(
+            "https://google.com/",
+            &Link::new("https://google.com/".to_string()),
+            time,
+        )
+        .unwrap();
+        assert_eq!(sink.pending, 1);
+
+        sink.on_link(
+            "https://google.com/",
+            &Link {
+                element: "a".to_string(),
+                anchor_text: "News".to_string(),
+                rel: vec!["nofollow".to_string()],
+                ..Link::new("https://google.com/news".to_string())
+            },
+            time,
+        )
+        .unwrap();
+        assert_eq!(sink.pending, 0);
+
+        sink.on_error(&Fetch {
+            url: "https://google.com/gone",
+            method: "GET",
+            attempt: 1,
+            status: None,
+            error: Some("timed out".to_string()),
+            duration: Duration::from_millis(1500),
+            time: time,
+        })
+        .unwrap();
+        sink.on_entry(&FeedEntry {
+            feed: "https://google.com/feed".to_string(),
+            url: "https://google.com/news".to_string(),
+            title: "News".to_string(),
+            ..FeedEntry::default()
+        })
+        .unwrap();
+        sink.close().unwrap();
+
+        let page: (String, i64, String, String) = sink
+            .connection
+            .query_row(
+                "SELECT title, status, text_digest, fetched_at FROM pages WHERE url = ?1",
+                params!["https://google.com"],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            page,
+            (
+                "Google".to_string(),
+                200,
+                digest(b"abc"),
+                "2018-05-01T12:00:00+00:00".to_string()
+            )
+        );
+        let redirect: (String, i64) = sink
+            .connection
+            .query_row(
+                "SELECT redirect, redirect_delay FROM pages",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(redirect, ("https://google.com/news".to_string(), 0));
+
+        let link: (String, String, String, String) = sink
+            .connection
+            .query_row(
+                "SELECT target, anchor, rel, kind FROM links",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            link,
+            (
+                "https://google.com/news".to_string(),
+                "News".to_string(),
+                "nofollow".to_string(),
+                "navigation".to_string()
+            )
+        );
+
+        let fetch: (Option<i64>, String, i64) = sink
+            .connection
+            .query_row(
+                "SELECT status, error, duration_ms FROM fetches",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(fetch, (None, "timed out".to_string(), 1500));
+
+        let entry: (String, String) = sink
+            .connection
+            .query_row(
+                "SELECT feed, title FROM feed_entries WHERE url = ?1",
+                params!["https://google.com/news"],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            entry,
+            ("https://google.com/feed".to_string(), "News".to_string())
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use charset;
+use document::{collapse_whitespace, CrawlResult, PageDocument};
+use handler::{content_type, ContentHandler};
+use html::normalize_link_text;
+use link::Link;
+use reqwest::header::Headers;
+use url::Url;
+
+// characters that can't be part of a URL written in text
+static URL_TERMINATORS: [char; 5] = ['<', '>', '"', '`', '\''];
+// characters that usually end the sentence a URL is in, rather than the URL
+static TRAILING_PUNCTUATION: [char; 10] = ['.', ',', ';', ':', '!', '?', '*', '_', '~', ']'];
+// how far to look for the ] of a link's text or the end of its title; CommonMark
+// caps labels at 999 characters
+static MAX_BRACKET_DISTANCE: usize = 1000;
+
+/// A `[text](url "title")` or `![alt](url)` in Markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkdownLink {
+    pub image: bool,
+    pub text: String,
+    pub url: String,
+    pub title: String,
+}
+
+/// Finds the `http://` and `https://` URLs written out in some text.
+pub fn find_bare_urls(text: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let lowercase = text.to_ascii_lowercase();
+    let mut position = 0;
+
+    while let Some(found) = lowercase[position..].find("http") {
+        let start = position + found;
+        position = start + 4;
+
+        let rest = &lowercase[start..];
+        if !(rest.starts_with("http://") || rest.starts_with("https://")) {
+            continue;
+        }
+        // the "http" in "xhttp://" isn't the start of a URL
+        if text[..start]
+            .chars()
+            .next_back()
+            .map_or(false, |x| x.is_alphanumeric())
+        {
+            continue;
+        }
+
+        let length = text[start..]
+            .find(|x: char| x.is_whitespace() || URL_TERMINATORS.contains(&x))
+            .unwrap_or(text.len() - start);
+        let mut url = &text[start..start + length];
+
+        // a closing parenthesis is only part of the URL if it has an opening one
+        loop {
+            let trimmed = url.trim_end_matches(|x| TRAILING_PUNCTUATION.contains(&x));
+            url = if trimmed.ends_with(')')
+                && trimmed.matches('(').count() < trimmed.matches(')').count()
+            {
+                &trimmed[..trimmed.len() - 1]
+            } else {
+                trimmed
+            };
+            if url == trimmed {
+                break;
+            }
+        }
+
+        position = start + url.len().max(4);
+        if url.len() > "https://".len() {
+            urls.push(url.to_string());
+        }
+    }
+
+    return urls;
+}
+
+// finds the ] that closes the [ at `start`, allowing nested brackets, but not
+// past the end of the paragraph or MAX_BRACKET_DISTANCE bytes (so text full of
+// unclosed brackets doesn't take quadratic time)
+fn closing_bracket(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = start;
+    let end = bytes.len().min(start + MAX_BRACKET_DISTANCE);
+
+    while position < end {
+        match bytes[position] {
+            b'\\' => position += 1,
+            b'\n' => {
+                let mut next = position + 1;
+                while next < end
+                    && (bytes[next] == b' ' || bytes[next] == b'\t' || bytes[next] == b'\r')
+                {
+                    next += 1;
+                }
+                if bytes.get(next) == Some(&b'\n') {
+                    return None;
+                }
+            }
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            _ => {}
+        }
+        position += 1;
+    }
+    return None;
+}
+
+// reads the (url "title") after a link's text, returning them and where it ends
+fn link_destination(markdown: &str, start: usize) -> Option<(String, String, usize)> {
+    let bytes = markdown.as_bytes();
+    let skip_spaces = |mut position: usize| {
+        while position < bytes.len() && (bytes[position] == b' ' || bytes[position] == b'\n') {
+            position += 1;
+        }
+        position
+    };
+
+    let mut position = skip_spaces(start);
+    let url_start;
+    let url_end;
+    if bytes.get(position) == Some(&b'<') {
+        url_start = position + 1;
+        url_end = url_start + markdown[url_start..].find(|x: char| x == '>' || x == '\n')?;
+        position = url_end + 1;
+    } else {
+        url_start = position;
+        let mut depth = 0;
+        while position < bytes.len() {
+            match bytes[position] {
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                c if c <= b' ' => break,
+                _ => {}
+            }
+            position += 1;
+        }
+        url_end = position;
+    }
+
+    position = skip_spaces(position);
+    let mut title = String::new();
+    if let Some(&quote) = bytes.get(position) {
+        let closing = match quote {
+            b'"' => Some(b'"'),
+            b'\'' => Some(b'\''),
+            b'(' => Some(b')'),
+            _ => None,
+        };
+        if let Some(closing) = closing {
+            let limit = bytes.len().min(position + 1 + MAX_BRACKET_DISTANCE);
+            let end = position
+                + 1
+                + bytes[position + 1..limit]
+                    .iter()
+                    .position(|x| *x == closing)?;
+            title = markdown[position + 1..end].to_string();
+            position = skip_spaces(end + 1);
+        }
+    }
+
+    if bytes.get(position) != Some(&b')') {
+        return None;
+    }
+    return Some((
+        markdown[url_start..url_end].to_string(),
+        title,
+        position + 1,
+    ));
+}
+
+/// Finds the inline links and images of some Markdown, and the URLs of its
+/// `[label]: url` reference definitions.
+pub fn find_markdown_links(markdown: &str) -> Vec<MarkdownLink> {
+    let bytes = markdown.as_bytes();
+    let mut links = Vec::new();
+    let mut position = 0;
+
+    while let Some(found) = markdown[position..].find('[') {
+        let start = position + found;
+        position = start + 1;
+        if start > 0 && bytes[start - 1] == b'\\' {
+            continue;
+        }
+
+        let end = match closing_bracket(bytes, start) {
+            Some(end) => end,
+            None => continue,
+        };
+        let text = collapse_whitespace(&markdown[start + 1..end]);
+        let image = start > 0 && bytes[start - 1] == b'!';
+
+        if bytes.get(end + 1) == Some(&b'(') {
+            if let Some((url, title, after)) = link_destination(markdown, end + 2) {
+                links.push(MarkdownLink {
+                    image: image,
+                    text: text,
+                    url: url,
+                    title: title,
+                });
+                position = after;
+            }
+        } else if bytes.get(end + 1) == Some(&b':')
+            && (start == 0 || markdown[..start].trim_end_matches(' ').ends_with('\n'))
+        {
+            let definition = markdown[end + 2..].lines().next().unwrap_or("").trim();
+            let url = definition.split_whitespace().next().unwrap_or("");
+            let url = url.trim_start_matches('<').trim_end_matches('>');
+            if url.len() != 0 {
+                links.push(MarkdownLink {
+                    image: false,
+                    text: String::new(),
+                    url: url.to_string(),
+                    title: String::new(),
+                });
+            }
+        }
+    }
+
+    return links;
+}
+
+// adds a link unless it's invalid or already there
+fn push_link(url: &Url, links: &mut Vec<Link>, mut link: Link) {
+    match url.join(&link.url) {
+        Ok(joined) => link.url = joined.to_string(),
+        Err(error) => {
+            debug!("bad URL {:?} in {}: {}", link.url, url, error);
+            return;
+        }
+    }
+
+    if !links.iter().any(|x| x.url == link.url) {
+        links.push(link);
+    }
+}
+
+fn text_result(url: &Url, text: &str, mut links: Vec<Link>, title: String) -> CrawlResult {
+    for found in find_bare_urls(text) {
+        let mut link = Link::new(found);
+        link.element = "text".to_string();
+        push_link(url, &mut links, link);
+    }
+
+    let text = collapse_whitespace(text);
+    let mut result = CrawlResult::new("text");
+    result.links = links;
+    result.document = Some(PageDocument {
+        title: title,
+        word_count: text.split_whitespace().count(),
+        text: text,
+        ..PageDocument::default()
+    });
+    return result;
+}
+
+fn is_markdown_path(url: &Url) -> bool {
+    let path = url.path().to_lowercase();
+    return path.ends_with(".md") || path.ends_with(".markdown");
+}
+
+/// Handles `text/plain`, following any URLs written in it. Markdown served as
+/// plain text (a `.md` file, say) is handed to `MarkdownHandler`.
+pub struct TextHandler;
+
+impl ContentHandler for TextHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "text/plain"
+    }
+
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult {
+        if is_markdown_path(url) {
+            return MarkdownHandler.process(url, headers, body);
+        }
+
+        let (text, _) = charset::decode(body, &content_type(headers));
+        return text_result(url, &text, Vec::new(), String::new());
+    }
+}
+
+/// Handles Markdown, following its links and images as well as bare URLs.
+pub struct MarkdownHandler;
+
+impl ContentHandler for MarkdownHandler {
+    fn accepts(&self, mime: &str) -> bool {
+        mime == "text/markdown" || mime == "text/x-markdown"
+    }
+
+    fn process(&self, url: &Url, headers: &Headers, body: &[u8]) -> CrawlResult {
+        let (text, _) = charset::decode(body, &content_type(headers));
+
+        let mut links = Vec::new();
+        for found in find_markdown_links(&text) {
+            let mut link = Link::new(found.url);
+            if found.image {
+                link.element = "img".to_string();
+                link.attribute = "src".to_string();
+                link.image_alt = normalize_link_text(&found.text);
+            } else {
+                link.element = "a".to_string();
+                link.attribute = "href".to_string();
+                link.anchor_text = normalize_link_text(&found.text);
+            }
+            link.title = normalize_link_text(&found.title);
+            push_link(url, &mut links, link);
+        }
+
+        // the first heading is as close as Markdown gets to a title
+        let title = text
+            .lines()
+            .map(|x| x.trim())
+            .find(|x| x.starts_with("# "))
+            .map(|x| collapse_whitespace(x.trim_start_matches('#').trim_end_matches('#')))
+            .unwrap_or(String::new());
+
+        let mut result = text_result(url, &text, links, title);
+        result.kind = "markdown".to_string();
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use text::*;
+
+    #[test]
+    fn _find_bare_urls() {
+        assert_eq!(
+            find_bare_urls(
+                "See https://google.com/news. Or (http://google.com/search?q=a_(b))!\n\
+                 <HTTPS://google.com/mail>, xhttp://no and https:// too"
+            ),
+            vec![
+                "https://google.com/news",
+                "http://google.com/search?q=a_(b)",
+                "HTTPS://google.com/mail",
+            ]
+        );
+        assert_eq!(find_bare_urls("nothing here"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn _find_markdown_links() {
+        assert_eq!(
+            find_markdown_links(
+                "Read [the *news*](https://google.com/news \"Top stories\") and\n\
+                 ![a [logo]](logo.png), not \\[this](x) or [that] (y).\n\
+                 [mail]: <https://google.com/mail> 'Gmail'\n\
+                 [a link with (parens)](wiki/A_(b))"
+            ),
+            vec![
+                MarkdownLink {
+                    image: false,
+                    text: "the *news*".to_string(),
+                    url: "https://google.com/news".to_string(),
+                    title: "Top stories".to_string(),
+                },
+                MarkdownLink {
+                    image: true,
+                    text: "a [logo]".to_string(),
+                    url: "logo.png".to_string(),
+                    title: String::new(),
+                },
+                MarkdownLink {
+                    image: false,
+                    text: String::new(),
+                    url: "https://google.com/mail".to_string(),
+                    title: String::new(),
+                },
+                MarkdownLink {
+                    image: false,
+                    text: "a link with (parens)".to_string(),
+                    url: "wiki/A_(b)".to_string(),
+                    title: String::new(),
+                },
+            ]
+        );
+
+        // link text doesn't go past a blank line, or on forever
+        assert_eq!(
+            find_markdown_links("[not\n \n a link](x) [a\nlink](y)")
+                .iter()
+                .map(|x| x.url.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["y"]
+        );
+        let long = format!("[{}](x)", "a".repeat(MAX_BRACKET_DISTANCE));
+        assert_eq!(find_markdown_links(&long), Vec::new());
+        assert_eq!(find_markdown_links(&"[".repeat(10_000)), Vec::new());
+    }
+
+    #[test]
+    fn _text_handler() {
+        let url = Url::parse("https://google.com/notes.txt").unwrap();
+        let result = TextHandler.process(
+            &url,
+            &Headers::new(),
+            b"Links:\n\thttps://google.com/news\n\nhttps://google.com/news again\n",
+        );
+
+        assert_eq!(result.kind, "text");
+        assert_eq!(result.links.len(), 1);
+        assert_eq!(result.links[0].url, "https://google.com/news");
+        assert_eq!(result.links[0].element, "text");
+        let document = result.document.unwrap();
+        assert_eq!(
+            document.text,
+            "Links: https://google.com/news https://google.com/news again"
+        );
+        assert_eq!(document.word_count, 4);
+
+        // .md files are often served as text/plain
+        let url = Url::parse("https://google.com/README.md").unwrap();
+        assert_eq!(
+            TextHandler.process(&url, &Headers::new(), b"# Hi").kind,
+            "markdown"
+        );
+    }
+
+    #[test]
+    fn _markdown_handler() {
+        let url = Url::parse("https://google.com/docs/").unwrap();
+        let result = MarkdownHandler.process(
+            &url,
+            &Headers::new(),
+            b"# Getting  started #\n\nSee [the guide](guide.md) and https://google.com/news.\n\
+              ![Logo](/logo.png)",
+        );
+
+        assert_eq!(result.kind, "markdown");
+        assert_eq!(
+            result.links,
+            vec![
+                Link {
+                    element: "a".to_string(),
+                    attribute: "href".to_string(),
+                    anchor_text: "the guide".to_string(),
+                    ..Link::new("https://google.com/docs/guide.md".to_string())
+                },
+                Link {
+                    element: "img".to_string(),
+                    attribute: "src".to_string(),
+                    image_alt: "Logo".to_string(),
+                    ..Link::new("https://google.com/logo.png".to_string())
+                },
+                Link {
+                    element: "text".to_string(),
+                    ..Link::new("https://google.com/news".to_string())
+                },
+            ]
+        );
+        assert_eq!(result.document.unwrap().title, "Getting started");
+
+        // titles get the same treatment as HTML ones, and don't run on forever
+        let result = MarkdownHandler.process(
+            &url,
+            &Headers::new(),
+            format!(
+                "[a](x \"Top\n\tstories\") [b](y '{}') [c](z \"{}\")",
+                "long ".repeat(100),
+                "x".repeat(2000)
+            )
+            .as_bytes(),
+        );
+        assert_eq!(result.links.len(), 2);
+        assert_eq!(result.links[0].title, "Top stories");
+        assert_eq!(result.links[1].title.chars().count(), 256);
+    }
+}
@@ -1,3 +1,4 @@
+use link::Link;
 use url::Url;
 
 pub fn repair_suggested_url(original_url: &Url, attribute: (&str, &str)) -> Option<Vec<String>> {
@@ -119,12 +120,24 @@ pub fn remove_get_params(mut url: Url) -> Url {
     return url;
 }
 
-pub fn add_urls_to_vec(urls: Option<Vec<String>>, into: &mut Vec<String>, cache: &Vec<String>) {
+pub fn add_urls_to_vec(
+    urls: Option<Vec<String>>,
+    link: &Link,
+    into: &mut Vec<Link>,
+    cache: &Vec<String>,
+) {
     if urls != None {
-        for url in urls.unwrap() {
-            if check_if_is_in_url_list(&url, &into) && check_if_is_in_url_list(&url, &cache) {
+        for (i, url) in urls.unwrap().into_iter().enumerate() {
+            if !into.iter().any(|x| x.url == url) && check_if_is_in_url_list(&url, &cache) {
                 trace!("found url {}", url);
-                into.push(url);
+                // anything after the first url is a guessed root domain, not the link itself
+                let mut found_link = if i == 0 {
+                    link.clone()
+                } else {
+                    Link::new(String::new())
+                };
+                found_link.url = url;
+                into.push(found_link);
             } else {
                 trace!("found duplicate url {}", url);
             }
@@ -132,6 +145,48 @@ pub fn add_urls_to_vec(urls: Option<Vec<String>>, into: &mut Vec<String>, cache:
     }
 }
 
+/// Finds the URLs in a `srcset` attribute, leaving out their width/density descriptors.
+pub fn parse_srcset(srcset: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = srcset;
+
+    loop {
+        rest = rest.trim_start_matches(|x: char| x.is_whitespace() || x == ',');
+        if rest.len() == 0 {
+            break;
+        }
+
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = &rest[..end];
+        rest = &rest[end..];
+
+        if url.ends_with(",") {
+            // no descriptors, the comma ends the candidate
+            urls.push(url.trim_end_matches(',').to_string());
+            continue;
+        }
+        urls.push(url.to_string());
+
+        // descriptors may contain commas inside parentheses (e.g. future ones like "1x (foo, bar)")
+        let mut depth = 0;
+        let mut consumed = rest.len();
+        for (i, c) in rest.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    consumed = i + 1;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        rest = &rest[consumed..];
+    }
+
+    return urls;
+}
+
 pub fn get_root_domain(url: &str) -> Option<String> {
     debug!("getting root domain for {}", url);
     let _parsed_url = Url::parse(url);
@@ -217,6 +272,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn _parse_srcset() {
+        fn strings(input: &[&str]) -> Vec<String> {
+            input.iter().map(|x| x.to_string()).collect()
+        }
+
+        assert_eq!(parse_srcset("image.png"), strings(&["image.png"]));
+        assert_eq!(
+            parse_srcset(" image.png 1x,\n image-2x.png 2x ,image-3x.png 3x"),
+            strings(&["image.png", "image-2x.png", "image-3x.png"])
+        );
+        assert_eq!(
+            parse_srcset("a.png, b.png 480w,c,d.png 800w"),
+            strings(&["a.png", "b.png", "c,d.png"])
+        );
+        assert_eq!(
+            parse_srcset("a.png 1x (x, y), b.png"),
+            strings(&["a.png", "b.png"])
+        );
+        assert_eq!(parse_srcset(" , "), strings(&[]));
+    }
+
     #[test]
     fn _get_root_domain() {
         assert_eq!(
@@ -231,22 +308,26 @@ mod tests {
 
     #[test]
     fn _add_url_to_vec() {
-        let mut fake_vec: Vec<String> = Vec::new();
+        let mut fake_vec: Vec<Link> = Vec::new();
+        let link = Link::new(String::new());
 
         add_urls_to_vec(
             Some(vec!["https://google.com".to_string()]),
+            &link,
             &mut fake_vec,
             &Vec::new(),
         );
         assert_eq!(fake_vec.len(), 1);
         add_urls_to_vec(
             Some(vec!["https://google.com".to_string()]),
+            &link,
             &mut fake_vec,
             &Vec::new(),
         );
         assert_eq!(fake_vec.len(), 1);
         add_urls_to_vec(
             Some(vec!["https://google.gl".to_string()]),
+            &link,
             &mut fake_vec,
             &Vec::new(),
         );
@@ -255,10 +336,27 @@ mod tests {
         let fake_cache: Vec<String> = vec!["https://google.pl".to_string()];
         add_urls_to_vec(
             Some(vec!["https://google.pl".to_string()]),
+            &link,
             &mut fake_vec,
             &fake_cache,
         );
         assert_eq!(fake_vec.len(), 2);
+
+        let mut nofollow = Link::new(String::new());
+        nofollow.rel = vec!["nofollow".to_string()];
+        add_urls_to_vec(
+            Some(vec![
+                "https://its.goggle.com/".to_string(),
+                "https://goggle.com/".to_string(),
+            ]),
+            &nofollow,
+            &mut fake_vec,
+            &Vec::new(),
+        );
+        assert_eq!(fake_vec[2].url, "https://its.goggle.com/");
+        assert_eq!(fake_vec[2].rel, vec!["nofollow".to_string()]);
+        assert_eq!(fake_vec[3].url, "https://goggle.com/");
+        assert_eq!(fake_vec[3].rel, Vec::<String>::new());
     }
 
     #[test]
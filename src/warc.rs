@@ -0,0 +1,691 @@
+use base32;
+use brotli_decompressor::Decompressor;
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sha1::Sha1;
+use std::fs::File;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use uuid::Uuid;
+
+/// The IIPC's recommended size for a WARC file.
+pub static DEFAULT_MAX_SIZE: u64 = 1_000_000_000;
+
+/// `sha1:` followed by the base32 SHA-1 of `data`, as used by `WARC-*-Digest`.
+pub fn digest(data: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(data);
+    return format!(
+        "sha1:{}",
+        base32::encode(
+            base32::Alphabet::RFC4648 { padding: true },
+            &sha1.digest().bytes()
+        )
+    );
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4().to_hyphenated())
+}
+
+fn warc_date(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Formats a record of the given type. `headers` are added after the
+/// `WARC-Type`, `WARC-Record-ID` and `WARC-Date` every record has.
+pub fn format_record(
+    warc_type: &str,
+    id: &str,
+    time: DateTime<Utc>,
+    headers: &[(&str, &str)],
+    content_type: &str,
+    block: &[u8],
+) -> Vec<u8> {
+    let mut record = format!(
+        "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: {}\r\nWARC-Date: {}\r\n",
+        warc_type,
+        id,
+        warc_date(time)
+    );
+    for &(name, value) in headers {
+        record.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    record.push_str(&format!(
+        "WARC-Block-Digest: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        digest(block),
+        content_type,
+        block.len()
+    ));
+
+    let mut record = record.into_bytes();
+    record.extend_from_slice(block);
+    record.extend_from_slice(b"\r\n\r\n");
+    return record;
+}
+
+/// Formats `name: value` lines for `warcinfo` and `metadata` records.
+pub fn format_fields(fields: &[(String, String)]) -> Vec<u8> {
+    let mut block = String::new();
+    for &(ref name, ref value) in fields {
+        // a newline would start a new field
+        block.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            value.replace(|c| c == '\r' || c == '\n', " ")
+        ));
+    }
+
+    return block.into_bytes();
+}
+
+/// One request sent and the response to it.
+pub struct Exchange<'a> {
+    pub url: &'a str,
+    pub time: DateTime<Utc>,
+    /// The HTTP request line and headers.
+    pub request: &'a [u8],
+    /// The HTTP status line and headers.
+    pub response_head: &'a [u8],
+    pub body: &'a [u8],
+    /// Why the body was cut short, for `WARC-Truncated`, or empty if it wasn't.
+    pub truncated: &'a str,
+    /// Fields for the `metadata` record, like outlinks.
+    pub metadata: &'a [(String, String)],
+    /// Whether the request and response head were rebuilt from what the HTTP
+    /// client reported, rather than captured as they were sent, which the
+    /// `metadata` record then says.
+    pub reconstructed: bool,
+}
+
+/// Writes `request`, `response` and `metadata` records for every fetch to
+/// `PREFIX-TIMESTAMP-SERIAL.warc.gz` files, each gzipped separately so readers
+/// can seek to any of them. A new file is started once one passes `max_size`.
+pub struct WarcWriter {
+    prefix: String,
+    max_size: u64,
+    file: Option<BufWriter<File>>,
+    warcinfo_id: String,
+    written: u64,
+    serial: u32,
+}
+
+impl WarcWriter {
+    pub fn new(prefix: String, max_size: u64) -> WarcWriter {
+        WarcWriter {
+            prefix: prefix,
+            max_size: max_size,
+            file: None,
+            warcinfo_id: String::new(),
+            written: 0,
+            serial: 0,
+        }
+    }
+
+    fn start_file(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        let time = Utc::now();
+        let filename = format!(
+            "{}-{}-{:05}.warc.gz",
+            self.prefix,
+            time.format("%Y%m%d%H%M%S"),
+            self.serial
+        );
+        self.serial += 1;
+        info!("writing WARC records to {}", filename);
+
+        self.file = Some(BufWriter::new(File::create(&filename)?));
+        self.written = 0;
+        self.warcinfo_id = record_id();
+
+        let block = format_fields(&[
+            (
+                "software".to_string(),
+                format!("crawler/{}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("format".to_string(), "WARC File Format 1.1".to_string()),
+            (
+                "conformsTo".to_string(),
+                "http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/"
+                    .to_string(),
+            ),
+            ("robots".to_string(), "obey".to_string()),
+        ]);
+        let name = filename.rsplit('/').next().unwrap().to_string();
+        let record = format_record(
+            "warcinfo",
+            &self.warcinfo_id.clone(),
+            time,
+            &[("WARC-Filename", name.as_str())],
+            "application/warc-fields",
+            &block,
+        );
+        return self.write_record(&record);
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(record)?;
+        let compressed = encoder.finish()?;
+
+        self.file.as_mut().unwrap().write_all(&compressed)?;
+        self.written += compressed.len() as u64;
+        return Ok(());
+    }
+
+    pub fn write_exchange(&mut self, exchange: &Exchange) -> io::Result<()> {
+        if self.file.is_none() || self.written >= self.max_size {
+            self.start_file()?;
+        }
+
+        let response_id = record_id();
+        let warcinfo_id = self.warcinfo_id.clone();
+        let payload_digest = digest(exchange.body);
+
+        let mut metadata = exchange.metadata.to_vec();
+        if exchange.reconstructed {
+            metadata.insert(
+                0,
+                ("http-messages".to_string(), "reconstructed".to_string()),
+            );
+        }
+
+        let mut response = exchange.response_head.to_vec();
+        response.extend_from_slice(exchange.body);
+
+        let mut response_headers = vec![
+            ("WARC-Target-URI", exchange.url),
+            ("WARC-Warcinfo-ID", warcinfo_id.as_str()),
+            ("WARC-Payload-Digest", payload_digest.as_str()),
+        ];
+        if exchange.truncated.len() != 0 {
+            response_headers.push(("WARC-Truncated", exchange.truncated));
+        }
+
+        let records = [
+            format_record(
+                "response",
+                &response_id,
+                exchange.time,
+                &response_headers,
+                "application/http;msgtype=response",
+                &response,
+            ),
+            format_record(
+                "request",
+                &record_id(),
+                exchange.time,
+                &[
+                    ("WARC-Target-URI", exchange.url),
+                    ("WARC-Warcinfo-ID", warcinfo_id.as_str()),
+                    ("WARC-Concurrent-To", response_id.as_str()),
+                ],
+                "application/http;msgtype=request",
+                exchange.request,
+            ),
+            format_record(
+                "metadata",
+                &record_id(),
+                exchange.time,
+                &[
+                    ("WARC-Target-URI", exchange.url),
+                    ("WARC-Warcinfo-ID", warcinfo_id.as_str()),
+                    ("WARC-Concurrent-To", response_id.as_str()),
+                ],
+                "application/warc-fields",
+                &format_fields(&metadata),
+            ),
+        ];
+
+        for record in records.iter() {
+            self.write_record(record)?;
+        }
+
+        return self.file.as_mut().unwrap().flush();
+    }
+}
+
+/// A record read back from a WARC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarcRecord {
+    pub headers: Vec<(String, String)>,
+    pub block: Vec<u8>,
+}
+
+impl WarcRecord {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(name))
+            .map(|x| x.1.as_str())
+    }
+}
+
+/// Reads the records of a WARC file, compressed or not.
+pub struct WarcReader<R: BufRead> {
+    reader: R,
+}
+
+impl WarcReader<Box<dyn BufRead>> {
+    pub fn open(path: &str) -> io::Result<WarcReader<Box<dyn BufRead>>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let gzipped = file.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+
+        let reader: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        return Ok(WarcReader::new(reader));
+    }
+}
+
+impl<R: BufRead> WarcReader<R> {
+    pub fn new(reader: R) -> WarcReader<R> {
+        WarcReader { reader: reader }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<WarcRecord>> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // skip the blank lines ending the last record
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().len() != 0 {
+                break;
+            }
+        }
+
+        if !line.starts_with("WARC/") {
+            return Err(invalid("expected a WARC record"));
+        }
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(invalid("WARC record ended in its headers"));
+            }
+
+            let header = line.trim_end();
+            if header.len() == 0 {
+                break;
+            }
+            if let Some(colon) = header.find(':') {
+                headers.push((
+                    header[..colon].trim().to_string(),
+                    header[colon + 1..].trim().to_string(),
+                ));
+            }
+        }
+
+        let mut record = WarcRecord {
+            headers: headers,
+            block: Vec::new(),
+        };
+        let length: u64 = record
+            .header("Content-Length")
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| invalid("WARC record without a Content-Length"))?;
+
+        (&mut self.reader)
+            .take(length)
+            .read_to_end(&mut record.block)?;
+        if (record.block.len() as u64) < length {
+            return Err(invalid("WARC record ended in its block"));
+        }
+
+        return Ok(Some(record));
+    }
+}
+
+impl<R: BufRead> Iterator for WarcReader<R> {
+    type Item = io::Result<WarcRecord>;
+
+    fn next(&mut self) -> Option<io::Result<WarcRecord>> {
+        match self.read_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+/// The HTTP response in a `response` record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The body, without any chunked, gzip, deflate or brotli encoding.
+    pub body: Vec<u8>,
+}
+
+pub fn parse_http_response(block: &[u8]) -> Option<HttpResponse> {
+    let head_end = block
+        .windows(4)
+        .position(|x| x == b"\r\n\r\n")
+        .map(|x| (x, x + 4))
+        .or_else(|| {
+            block
+                .windows(2)
+                .position(|x| x == b"\n\n")
+                .map(|x| (x, x + 2))
+        })?;
+
+    let head = String::from_utf8_lossy(&block[..head_end.0]).into_owned();
+    let mut lines = head.lines();
+
+    // HTTP/1.1 200 OK
+    let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+    let mut headers = Vec::new();
+    for line in lines {
+        if let Some(colon) = line.find(':') {
+            headers.push((
+                line[..colon].trim().to_string(),
+                line[colon + 1..].trim().to_string(),
+            ));
+        }
+    }
+
+    // every value of a header, in order, from however many lines it's on
+    let header_values = |name: &str| -> Vec<String> {
+        headers
+            .iter()
+            .filter(|x| x.0.eq_ignore_ascii_case(name))
+            .flat_map(|x| x.1.split(','))
+            .map(|x| x.trim().to_lowercase())
+            .filter(|x| x.len() != 0)
+            .collect()
+    };
+
+    let mut body = block[head_end.1..].to_vec();
+    if header_values("Transfer-Encoding")
+        .iter()
+        .any(|x| x == "chunked")
+    {
+        body = dechunk(&body)?;
+    }
+    // content codings are listed in the order they were applied
+    for coding in header_values("Content-Encoding").iter().rev() {
+        body = decode_content(body, coding);
+    }
+
+    return Some(HttpResponse {
+        status: status,
+        headers: headers,
+        body: body,
+    });
+}
+
+// whether a body starts with a zlib header: the deflate method and a checksum
+fn is_zlib(body: &[u8]) -> bool {
+    body.len() >= 2 && body[0] & 0x0f == 8 && (body[0] as u16 * 256 + body[1] as u16) % 31 == 0
+}
+
+// undoes one content coding, keeping what's there (and saying so) if it can't
+fn decode_content(body: Vec<u8>, coding: &str) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    let result = match coding {
+        "gzip" | "x-gzip" => MultiGzDecoder::new(&body[..]).read_to_end(&mut decoded),
+        // deflate is meant to be zlib-wrapped, but plenty of servers send it raw
+        "deflate" if is_zlib(&body) => ZlibDecoder::new(&body[..]).read_to_end(&mut decoded),
+        "deflate" => DeflateDecoder::new(&body[..]).read_to_end(&mut decoded),
+        "br" => Decompressor::new(&body[..], 4096).read_to_end(&mut decoded),
+        "identity" => return body,
+        _ => {
+            warn!(
+                "unknown content coding {:?}, keeping the body as it is",
+                coding
+            );
+            return body;
+        }
+    };
+
+    match result {
+        Ok(_) => decoded,
+        // a capture that was cut off still decodes up to where it stops
+        Err(ref error) if decoded.len() != 0 => {
+            warn!(
+                "error decoding {} body ({}), keeping the {} bytes before it",
+                coding,
+                error,
+                decoded.len()
+            );
+            decoded
+        }
+        Err(error) => {
+            warn!(
+                "error decoding {} body ({}), keeping it as it is",
+                coding, error
+            );
+            body
+        }
+    }
+}
+
+fn dechunk(mut chunked: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+
+    loop {
+        let line_end = chunked.windows(2).position(|x| x == b"\r\n")?;
+        let size_line = String::from_utf8_lossy(&chunked[..line_end]).into_owned();
+        // chunk extensions come after a ;
+        let size = usize::from_str_radix(size_line.split(';').next()?.trim(), 16).ok()?;
+        chunked = &chunked[line_end + 2..];
+
+        if size == 0 {
+            return Some(body);
+        }
+        if chunked.len() < size {
+            // keep what's there of a cut-off capture
+            body.extend_from_slice(chunked);
+            return Some(body);
+        }
+
+        body.extend_from_slice(&chunked[..size]);
+        chunked = &chunked[size..];
+        if chunked.starts_with(b"\r\n") {
+            chunked = &chunked[2..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warc::*;
+    use flate2::write::{DeflateEncoder, ZlibEncoder};
+    use std::fs;
+
+    #[test]
+    fn _digest() {
+        assert_eq!(digest(b""), "sha1:3I42H3S6NNFQ2MSVX7XZKYAYSCX5QBYJ");
+        assert_eq!(digest(b"abc"), "sha1:VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5");
+    }
+
+    #[test]
+    fn _format_record() {
+        let time = "2018-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let record = format_record(
+            "request",
+            "<urn:uuid:1>",
+            time,
+            &[("WARC-Target-URI", "https://google.com/")],
+            "application/http;msgtype=request",
+            b"abc",
+        );
+
+        assert_eq!(
+            String::from_utf8(record).unwrap(),
+            "WARC/1.1\r\n\
+             WARC-Type: request\r\n\
+             WARC-Record-ID: <urn:uuid:1>\r\n\
+             WARC-Date: 2018-05-01T12:00:00Z\r\n\
+             WARC-Target-URI: https://google.com/\r\n\
+             WARC-Block-Digest: sha1:VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5\r\n\
+             Content-Type: application/http;msgtype=request\r\n\
+             Content-Length: 3\r\n\
+             \r\n\
+             abc\r\n\r\n"
+        );
+        assert_eq!(
+            format_fields(&[("outlink".to_string(), "https://a.com/\nb".to_string())]),
+            b"outlink: https://a.com/ b\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn _write_exchange() {
+        let directory = ::std::env::temp_dir().join(format!("crawler-warc-{}", Uuid::new_v4()));
+        fs::create_dir(&directory).unwrap();
+
+        let prefix = directory.join("test").to_str().unwrap().to_string();
+        let mut writer = WarcWriter::new(prefix, 1);
+        let exchange = Exchange {
+            url: "https://google.com/",
+            time: Utc::now(),
+            request: b"GET / HTTP/1.1\r\nHost: google.com\r\n\r\n",
+            response_head: b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n",
+            body: b"abc",
+            truncated: "length",
+            metadata: &[("outlink".to_string(), "https://google.com/news".to_string())],
+            reconstructed: true,
+        };
+        writer.write_exchange(&exchange).unwrap();
+        writer.write_exchange(&exchange).unwrap();
+
+        // every exchange goes over the 1 byte limit, so each gets its own file
+        let mut files: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .collect();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].to_str().unwrap().ends_with("-00000.warc.gz"));
+
+        let mut contents = String::new();
+        MultiGzDecoder::new(File::open(&files[0]).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let types: Vec<&str> = contents
+            .lines()
+            .filter(|x| x.starts_with("WARC-Type: "))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "WARC-Type: warcinfo",
+                "WARC-Type: response",
+                "WARC-Type: request",
+                "WARC-Type: metadata",
+            ]
+        );
+        assert!(contents.contains("WARC-Payload-Digest: sha1:VGMT4NSHA2AWVOR6EVYXQUGCNSONBWE5\r\n"));
+        assert!(contents.contains("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nabc\r\n\r\n"));
+        assert!(contents
+            .contains("http-messages: reconstructed\r\noutlink: https://google.com/news\r\n"));
+        assert!(contents.contains("WARC-Truncated: length\r\n"));
+
+        let response_id = contents
+            .lines()
+            .filter(|x| x.starts_with("WARC-Record-ID: "))
+            .nth(1)
+            .unwrap()
+            .replace("WARC-Record-ID", "WARC-Concurrent-To");
+        assert_eq!(contents.matches(response_id.as_str()).count(), 2);
+    }
+
+    #[test]
+    fn _warc_reader() {
+        let time = "2018-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut input = format_record("request", "<urn:uuid:1>", time, &[], "text/plain", b"abc");
+        input.extend(format_record(
+            "response",
+            "<urn:uuid:2>",
+            time,
+            &[("WARC-Target-URI", "https://google.com/")],
+            "text/plain",
+            b"line\r\n\r\n",
+        ));
+
+        let records: Vec<WarcRecord> = WarcReader::new(&input[..]).map(|x| x.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].header("warc-type"), Some("request"));
+        assert_eq!(records[0].block, b"abc".to_vec());
+        assert_eq!(
+            records[1].header("WARC-Target-URI"),
+            Some("https://google.com/")
+        );
+        assert_eq!(records[1].block, b"line\r\n\r\n".to_vec());
+
+        let mut reader = WarcReader::new(&b"WARC/1.1\r\nContent-Length: 10\r\n\r\nabc"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(WarcReader::new(&b"<html>"[..]).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn _parse_http_response() {
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 404 Not Found\r\nContent-Type: text/html\r\n\r\n<p>"),
+            Some(HttpResponse {
+                status: 404,
+                headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+                body: b"<p>".to_vec(),
+            })
+        );
+        assert_eq!(
+            parse_http_response(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n"
+            )
+            .unwrap()
+            .body,
+            b"abcde".to_vec()
+        );
+
+        let mut encoder = GzEncoder::new(
+            b"HTTP/1.1 200 OK\nContent-Encoding: gzip\n\n".to_vec(),
+            Compression::default(),
+        );
+        encoder.write_all(b"abc").unwrap();
+        assert_eq!(
+            parse_http_response(&encoder.finish().unwrap())
+                .unwrap()
+                .body,
+            b"abc".to_vec()
+        );
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"abc").unwrap();
+        let zlib = encoder.finish().unwrap();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"abc").unwrap();
+        let raw = encoder.finish().unwrap();
+        for deflated in &[zlib, raw] {
+            let mut block = b"HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\n\r\n".to_vec();
+            block.extend_from_slice(deflated);
+            assert_eq!(parse_http_response(&block).unwrap().body, b"abc".to_vec());
+        }
+
+        // a body that isn't what it says it is is kept rather than dropped
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\nabc")
+                .unwrap()
+                .body,
+            b"abc".to_vec()
+        );
+
+        assert_eq!(parse_http_response(b"not http"), None);
+        assert_eq!(parse_http_response(b"HTTP/1.1 OK\r\n\r\n"), None);
+    }
+}